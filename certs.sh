@@ -0,0 +1,24 @@
+#!/bin/sh
+# Self-signed certificates for docker-compose.ssl.yml. Writes a CA (ca.pem)
+# and a broker keystore for localhost into ./secrets. Needs openssl and keytool.
+set -e
+
+pass=k12-secret
+mkdir -p secrets
+cd secrets
+rm -f ca.* kafka.*
+
+openssl req -new -x509 -nodes -days 365 -subj "/CN=k12-ca" \
+  -keyout ca.key -out ca.pem
+keytool -genkeypair -noprompt -alias kafka -keyalg RSA -validity 365 \
+  -dname "CN=localhost" -keystore kafka.keystore.jks \
+  -storepass $pass -keypass $pass
+keytool -certreq -alias kafka -keystore kafka.keystore.jks \
+  -storepass $pass -file kafka.csr
+echo "subjectAltName=DNS:localhost,DNS:kafka" > kafka.ext
+openssl x509 -req -days 365 -in kafka.csr -CA ca.pem -CAkey ca.key \
+  -CAcreateserial -extfile kafka.ext -out kafka.pem
+keytool -importcert -noprompt -alias ca -file ca.pem \
+  -keystore kafka.keystore.jks -storepass $pass
+keytool -importcert -noprompt -alias kafka -file kafka.pem \
+  -keystore kafka.keystore.jks -storepass $pass
@@ -0,0 +1,199 @@
+use crate::client::{K12Context, KafkaConfig};
+use clap::ArgMatches;
+use rdkafka::admin::{
+    AdminClient, AdminOptions, AlterConfig, ConfigEntry, ConfigResource, ConfigSource,
+    OwnedResourceSpecifier, ResourceSpecifier,
+};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::topic::split_config;
+use crate::common::{Format, FormatConfig};
+
+pub async fn config<const OAUTH: bool>(
+    client_config: KafkaConfig,
+    timeout: Duration,
+    format_config: FormatConfig,
+    matches: &ArgMatches<'static>,
+) {
+    let admin: AdminClient<K12Context<OAUTH>> = client_config.create().unwrap_or_else(|err| {
+        eprintln!("Could not create admin client: {}", err);
+        std::process::exit(1);
+    });
+    let opts = AdminOptions::new().request_timeout(Some(timeout));
+
+    let (name, matches) = matches.subcommand();
+    let matches = matches.expect("config subcommand");
+    let broker_id = matches.value_of("broker").map(|broker| {
+        broker.parse::<i32>().unwrap_or_else(|err| {
+            eprintln!("Invalid broker id: {}", err);
+            std::process::exit(1);
+        })
+    });
+    let specifier = match (matches.value_of("topic"), broker_id) {
+        (Some(topic), _) => ResourceSpecifier::Topic(topic),
+        (None, Some(broker_id)) => ResourceSpecifier::Broker(broker_id),
+        (None, None) => unreachable!("clap requires --topic or --broker"),
+    };
+    let resource = describe(&admin, specifier, &opts).await;
+
+    let updates: HashMap<&str, Option<&str>> = match name {
+        "describe" => {
+            print_resource(&resource, &format_config);
+            std::process::exit(0);
+        }
+        "set" => matches
+            .values_of("entry")
+            .expect("entries are required")
+            .map(split_config)
+            .map(|(key, value)| (key, Some(value)))
+            .collect(),
+        "reset" => matches
+            .values_of("key")
+            .expect("keys are required")
+            .map(|key| (key, None))
+            .collect(),
+        _ => unreachable!(),
+    };
+
+    // alter_configs replaces the whole dynamic config of the resource, so start
+    // from what is set today and apply the changes on top.
+    let dynamic_source = match specifier {
+        ResourceSpecifier::Broker(_) => ConfigSource::DynamicBroker,
+        _ => ConfigSource::DynamicTopic,
+    };
+    let mut entries: HashMap<&str, &str> = HashMap::new();
+    for entry in &resource.entries {
+        if entry.source != dynamic_source || entry.is_read_only {
+            continue;
+        }
+        match entry.value.as_deref() {
+            Some(value) => {
+                entries.insert(&entry.name, value);
+            }
+            None if !updates.contains_key(entry.name.as_str()) => {
+                eprintln!(
+                    "{} is sensitive and cannot be read back. Set it again in the same call to keep it.",
+                    entry.name
+                );
+                std::process::exit(1);
+            }
+            None => {}
+        }
+    }
+    for (key, value) in &updates {
+        if let Some(entry) = resource.get(key).filter(|entry| entry.is_read_only) {
+            eprintln!("{} is read-only.", entry.name);
+            std::process::exit(1);
+        }
+        match value {
+            Some(value) => entries.insert(key, value),
+            None => entries.remove(key),
+        };
+    }
+
+    let alter = AlterConfig { specifier, entries };
+    let results = admin
+        .alter_configs([&alter], &opts)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Admin request failed: {}", err);
+            std::process::exit(1);
+        });
+    let mut failed = false;
+    for result in results {
+        match result {
+            Ok(specifier) => println!("{}: ok", resource_name(&specifier)),
+            Err((specifier, err)) => {
+                failed = true;
+                eprintln!("{}: {}", resource_name(&specifier), err);
+            }
+        }
+    }
+    std::process::exit(if failed { 1 } else { 0 });
+}
+
+async fn describe<const OAUTH: bool>(
+    admin: &AdminClient<K12Context<OAUTH>>,
+    specifier: ResourceSpecifier<'_>,
+    opts: &AdminOptions,
+) -> ConfigResource {
+    let mut results = admin
+        .describe_configs([&specifier], opts)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("Admin request failed: {}", err);
+            std::process::exit(1);
+        });
+    match results.pop() {
+        Some(Ok(resource)) => resource,
+        Some(Err(err)) => {
+            eprintln!("Could not describe {:?}: {}", specifier, err);
+            std::process::exit(1);
+        }
+        None => {
+            eprintln!("Could not describe {:?}: no result", specifier);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn resource_name(specifier: &OwnedResourceSpecifier) -> String {
+    match specifier {
+        OwnedResourceSpecifier::Topic(name) => format!("topic {name}"),
+        OwnedResourceSpecifier::Group(name) => format!("group {name}"),
+        OwnedResourceSpecifier::Broker(id) => format!("broker {id}"),
+    }
+}
+
+fn print_resource(resource: &ConfigResource, format_config: &FormatConfig) {
+    let mut entries: Vec<&ConfigEntry> = resource.entries.iter().collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    match format_config.format {
+        Format::Json => {
+            let (resource_type, name) = match &resource.specifier {
+                OwnedResourceSpecifier::Topic(name) => ("topic", json!(name)),
+                OwnedResourceSpecifier::Group(name) => ("group", json!(name)),
+                OwnedResourceSpecifier::Broker(id) => ("broker", json!(id)),
+            };
+            let json = json!({
+                "resource": {
+                    "type": resource_type,
+                    "name": name,
+                },
+                "entries": entries.iter().map(|entry| json!({
+                    "name": entry.name,
+                    "value": entry.value,
+                    "source": format!("{:?}", entry.source),
+                    "default": entry.is_default,
+                    "sensitive": entry.is_sensitive,
+                    "read_only": entry.is_read_only,
+                })).collect::<Vec<_>>(),
+            });
+            println!("{json}");
+        }
+        Format::Raw => {
+            println!("{}:", resource_name(&resource.specifier));
+            for entry in entries {
+                let mut flags = Vec::new();
+                if entry.is_default {
+                    flags.push("default");
+                }
+                if entry.is_sensitive {
+                    flags.push("sensitive");
+                }
+                if entry.is_read_only {
+                    flags.push("read-only");
+                }
+                println!(
+                    "  {} = {}  Source: {:?}  {}",
+                    entry.name,
+                    entry.value.as_deref().unwrap_or("-"),
+                    entry.source,
+                    flags.join(" ")
+                );
+            }
+        }
+    }
+}
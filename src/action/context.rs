@@ -0,0 +1,116 @@
+use clap::ArgMatches;
+use serde_json::json;
+use toml_edit::{value, DocumentMut};
+
+use crate::common::{Format, FormatConfig};
+use crate::context::{context_file_path, load_context_file, Context};
+
+pub fn context(selected: Option<&str>, format_config: FormatConfig, matches: &ArgMatches<'static>) {
+    let file = load_context_file();
+    let active = selected.or(file.current_context.as_deref());
+    match matches.subcommand() {
+        ("list", _) => match format_config.format {
+            Format::Json => {
+                let json = json!({
+                    "current": active,
+                    "contexts": file.contexts.keys().collect::<Vec<_>>(),
+                });
+                println!("{json}");
+            }
+            Format::Raw => {
+                for name in file.contexts.keys() {
+                    let marker = if Some(name.as_str()) == active {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    println!("{marker} {name}");
+                }
+            }
+        },
+        ("switch", Some(matches)) => {
+            let name = matches.value_of("name").expect("context name is required");
+            if !file.contexts.contains_key(name) {
+                eprintln!("No context {name} in {}.", context_file_path().display());
+                std::process::exit(1);
+            }
+            switch(name);
+            println!("Switched to context {name}.");
+        }
+        ("show", Some(matches)) => {
+            let Some(name) = matches.value_of("name").or(active) else {
+                eprintln!("No context selected.");
+                std::process::exit(1);
+            };
+            let Some(context) = file.contexts.get(name) else {
+                eprintln!("No context {name} in {}.", context_file_path().display());
+                std::process::exit(1);
+            };
+            show(name, context, &format_config);
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Set `current-context`, keeping the rest of the file as it was written.
+fn switch(name: &str) {
+    let path = context_file_path();
+    let content = std::fs::read_to_string(&path).unwrap_or_default();
+    let mut document: DocumentMut = content.parse().unwrap_or_else(|err| {
+        eprintln!("Invalid contexts file {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+    document["current-context"] = value(name);
+    std::fs::write(&path, document.to_string()).unwrap_or_else(|err| {
+        eprintln!("Could not write {}: {}", path.display(), err);
+        std::process::exit(1);
+    });
+}
+
+/// Hide passwords and secrets, such as `sasl-password` or the
+/// `sasl.oauthbearer.client.secret` property.
+fn mask_secrets(settings: &mut serde_json::Map<String, serde_json::Value>) {
+    for (key, value) in settings.iter_mut() {
+        if key.contains("password") || key.contains("secret") {
+            *value = json!("********");
+        }
+    }
+}
+
+fn show(name: &str, context: &Context, format_config: &FormatConfig) {
+    let mut settings = serde_json::to_value(context).expect("context serializes");
+    if let Some(settings) = settings.as_object_mut() {
+        settings.retain(|_, value| !value.is_null());
+        mask_secrets(settings);
+        if let Some(properties) = settings
+            .get_mut("properties")
+            .and_then(serde_json::Value::as_object_mut)
+        {
+            mask_secrets(properties);
+        }
+    }
+    match format_config.format {
+        Format::Json => {
+            let json = json!({
+                "name": name,
+                "settings": settings,
+            });
+            println!("{json}");
+        }
+        Format::Raw => {
+            println!("Context: {name}");
+            for (key, value) in settings.as_object().into_iter().flatten() {
+                match value {
+                    serde_json::Value::Object(properties) => {
+                        println!("  {key}:");
+                        for (key, value) in properties {
+                            println!("    {key} = {}", value.as_str().unwrap_or_default());
+                        }
+                    }
+                    serde_json::Value::String(value) => println!("  {key} = {value}"),
+                    value => println!("  {key} = {value}"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use crate::client::{K12Context, KafkaConfig};
+use clap::ArgMatches;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::groups::GroupInfo;
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::Offset;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use crate::common::{Format, FormatConfig};
+
+pub fn groups<const OAUTH: bool>(
+    config: KafkaConfig,
+    timeout: Duration,
+    format_config: FormatConfig,
+    matches: &ArgMatches<'static>,
+) {
+    let consumer: BaseConsumer<K12Context<OAUTH>> = create_consumer(&config);
+    let names: Vec<&str> = matches
+        .values_of("name")
+        .map(Iterator::collect)
+        .unwrap_or_default();
+
+    if names.is_empty() {
+        let group_list = consumer
+            .fetch_group_list(None, timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to fetch group list: {}", err);
+                std::process::exit(1);
+            });
+        let mut groups: Vec<&GroupInfo> = group_list.groups().iter().collect();
+        groups.sort_by_key(|group| group.name());
+        match format_config.format {
+            Format::Json => {
+                let json = json!({
+                    "groups": groups.iter().map(|group| json!({
+                        "name": group.name(),
+                        "state": group.state(),
+                        "protocol_type": group.protocol_type(),
+                        "protocol": group.protocol(),
+                        "members": group.members().len(),
+                    })).collect::<Vec<_>>(),
+                });
+                println!("{json}");
+            }
+            Format::Raw => {
+                println!("Groups:");
+                for group in groups {
+                    println!(
+                        "  {}  State: {}  Protocol: {}  Members: {}",
+                        group.name(),
+                        group.state(),
+                        group.protocol(),
+                        group.members().len()
+                    );
+                }
+            }
+        }
+        std::process::exit(0);
+    }
+
+    let mut described = Vec::with_capacity(names.len());
+    for name in names {
+        let group_list = consumer
+            .fetch_group_list(Some(name), timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to describe group {}: {}", name, err);
+                std::process::exit(1);
+            });
+        let Some(group) = group_list.groups().first() else {
+            eprintln!("Group {name} not found.");
+            std::process::exit(1);
+        };
+        let members: Vec<Member> = group
+            .members()
+            .iter()
+            .map(|member| Member {
+                id: member.id().to_string(),
+                client_id: member.client_id().to_string(),
+                host: member.client_host().to_string(),
+                assignment: match group.protocol_type() {
+                    "consumer" => member
+                        .assignment()
+                        .and_then(parse_assignment)
+                        .unwrap_or_default(),
+                    _ => BTreeMap::new(),
+                },
+            })
+            .collect();
+        let lag = group_lag(&config, &consumer, name, &members, timeout);
+        described.push(GroupDescription {
+            name: name.to_string(),
+            state: group.state().to_string(),
+            protocol: group.protocol().to_string(),
+            protocol_type: group.protocol_type().to_string(),
+            members,
+            lag,
+        });
+    }
+
+    match format_config.format {
+        Format::Json => {
+            let json = json!({
+                "groups": described.iter().map(GroupDescription::to_json).collect::<Vec<_>>(),
+            });
+            println!("{json}");
+        }
+        Format::Raw => {
+            for group in described {
+                group.print();
+            }
+        }
+    }
+    std::process::exit(0);
+}
+
+pub(crate) fn create_consumer<const OAUTH: bool>(
+    config: &KafkaConfig,
+) -> BaseConsumer<K12Context<OAUTH>> {
+    config.create().unwrap_or_else(|err| {
+        eprintln!("Could not create consumer: {}", err);
+        std::process::exit(1);
+    })
+}
+
+struct Member {
+    id: String,
+    client_id: String,
+    host: String,
+    assignment: BTreeMap<String, Vec<i32>>,
+}
+
+struct PartitionLag {
+    partition: i32,
+    committed: i64,
+    high: i64,
+    member: Option<String>,
+}
+impl PartitionLag {
+    fn lag(&self) -> i64 {
+        (self.high - self.committed).max(0)
+    }
+}
+
+struct GroupDescription {
+    name: String,
+    state: String,
+    protocol: String,
+    protocol_type: String,
+    members: Vec<Member>,
+    lag: BTreeMap<String, Vec<PartitionLag>>,
+}
+impl GroupDescription {
+    fn print(&self) {
+        println!(
+            "Group: {}  State: {}  Protocol: {}  Members: {}",
+            self.name,
+            self.state,
+            self.protocol,
+            self.members.len()
+        );
+        for member in &self.members {
+            println!(
+                "  Member: {}  Client: {}  Host: {}",
+                member.id, member.client_id, member.host
+            );
+            for (topic, partitions) in &member.assignment {
+                println!("    {}: {:?}", topic, partitions);
+            }
+        }
+        for (topic, partitions) in &self.lag {
+            let total: i64 = partitions.iter().map(PartitionLag::lag).sum();
+            println!("  Topic: {}  Lag: {}", topic, total);
+            for partition in partitions {
+                println!(
+                    "    Partition: {}  Committed: {}  High: {}  Lag: {}  Member: {}",
+                    partition.partition,
+                    partition.committed,
+                    partition.high,
+                    partition.lag(),
+                    partition.member.as_deref().unwrap_or("-")
+                );
+            }
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "name": self.name,
+            "state": self.state,
+            "protocol_type": self.protocol_type,
+            "protocol": self.protocol,
+            "members": self.members.iter().map(|member| json!({
+                "id": member.id,
+                "client_id": member.client_id,
+                "host": member.host,
+                "assignment": member.assignment,
+            })).collect::<Vec<_>>(),
+            "topics": self.lag.iter().map(|(topic, partitions)| json!({
+                "topic": topic,
+                "lag": partitions.iter().map(PartitionLag::lag).sum::<i64>(),
+                "partitions": partitions.iter().map(|partition| json!({
+                    "partition": partition.partition,
+                    "committed": partition.committed,
+                    "high": partition.high,
+                    "lag": partition.lag(),
+                    "member": partition.member,
+                })).collect::<Vec<_>>(),
+            })).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Committed offset, high watermark and owning member for every partition the
+/// group has committed to. Committed offsets are looked up for all topics in
+/// the cluster so groups without members still report their lag.
+fn group_lag<const OAUTH: bool>(
+    config: &KafkaConfig,
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    name: &str,
+    members: &[Member],
+    timeout: Duration,
+) -> BTreeMap<String, Vec<PartitionLag>> {
+    let committed = committed_offsets(config, consumer, name, timeout);
+    let owners: HashMap<(&str, i32), &str> = members
+        .iter()
+        .flat_map(|member| {
+            member
+                .assignment
+                .iter()
+                .flat_map(move |(topic, partitions)| {
+                    partitions
+                        .iter()
+                        .map(move |partition| ((topic.as_str(), *partition), member.id.as_str()))
+                })
+        })
+        .collect();
+
+    let mut lag: BTreeMap<String, Vec<PartitionLag>> = BTreeMap::new();
+    for elem in committed.elements() {
+        let Offset::Offset(offset) = elem.offset() else {
+            continue;
+        };
+        let (_, high) = consumer
+            .fetch_watermarks(elem.topic(), elem.partition(), timeout)
+            .unwrap_or_else(|err| {
+                eprintln!(
+                    "error partition watermark {}/{}: {:?}",
+                    elem.topic(),
+                    elem.partition(),
+                    err
+                );
+                std::process::exit(1);
+            });
+        lag.entry(elem.topic().to_string())
+            .or_default()
+            .push(PartitionLag {
+                partition: elem.partition(),
+                committed: offset,
+                high,
+                member: owners
+                    .get(&(elem.topic(), elem.partition()))
+                    .map(ToString::to_string),
+            });
+    }
+    for partitions in lag.values_mut() {
+        partitions.sort_by_key(|partition| partition.partition);
+    }
+    lag
+}
+
+/// The offsets `group` has committed across every non-internal topic.
+/// Partitions without a commit come back as `Offset::Invalid`.
+pub(crate) fn committed_offsets<const OAUTH: bool>(
+    config: &KafkaConfig,
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    group: &str,
+    timeout: Duration,
+) -> TopicPartitionList {
+    let metadata = consumer
+        .fetch_metadata(None, timeout)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to fetch metadata: {}", err);
+            std::process::exit(1);
+        });
+    let mut tpl = TopicPartitionList::new();
+    for topic in metadata.topics() {
+        if topic.name().starts_with("__") {
+            continue;
+        }
+        for partition in topic.partitions() {
+            tpl.add_partition(topic.name(), partition.id());
+        }
+    }
+    let group_consumer = create_consumer::<OAUTH>(config.clone().set("group.id", group));
+    group_consumer
+        .committed_offsets(tpl, timeout)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to fetch committed offsets for {}: {}", group, err);
+            std::process::exit(1);
+        })
+}
+
+/// Decode a consumer protocol member assignment: a version, then topics each
+/// with their partitions, then opaque user data.
+fn parse_assignment(bytes: &[u8]) -> Option<BTreeMap<String, Vec<i32>>> {
+    let mut reader = ProtocolReader { bytes };
+    let _version = reader.i16()?;
+    let topic_count = reader.i32()?;
+    let mut assignment = BTreeMap::new();
+    for _ in 0..topic_count.max(0) {
+        let topic = reader.string()?;
+        let partition_count = reader.i32()?;
+        let partitions = (0..partition_count.max(0))
+            .map(|_| reader.i32())
+            .collect::<Option<Vec<i32>>>()?;
+        assignment.insert(topic, partitions);
+    }
+    Some(assignment)
+}
+
+struct ProtocolReader<'a> {
+    bytes: &'a [u8],
+}
+impl ProtocolReader<'_> {
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, rest) = self.bytes.split_first_chunk::<N>()?;
+        self.bytes = rest;
+        Some(*head)
+    }
+    fn i16(&mut self) -> Option<i16> {
+        self.take().map(i16::from_be_bytes)
+    }
+    fn i32(&mut self) -> Option<i32> {
+        self.take().map(i32::from_be_bytes)
+    }
+    fn string(&mut self) -> Option<String> {
+        let len = usize::try_from(self.i16()?).ok()?;
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(head.to_vec()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assignment(topics: &[(&str, &[i32])]) -> Vec<u8> {
+        let mut bytes = 0i16.to_be_bytes().to_vec();
+        bytes.extend((topics.len() as i32).to_be_bytes());
+        for (topic, partitions) in topics {
+            bytes.extend((topic.len() as i16).to_be_bytes());
+            bytes.extend(topic.as_bytes());
+            bytes.extend((partitions.len() as i32).to_be_bytes());
+            for partition in *partitions {
+                bytes.extend(partition.to_be_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn valid() {
+        let mut bytes = assignment(&[("orders", &[0, 2]), ("audit", &[])]);
+        // Trailing user data is ignored.
+        bytes.extend([0, 0, 0, 1, 42]);
+        let parsed = parse_assignment(&bytes).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["orders"], [0, 2]);
+        assert!(parsed["audit"].is_empty());
+        assert!(parse_assignment(&assignment(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = assignment(&[("orders", &[0, 2])]);
+        for len in 0..bytes.len() {
+            assert_eq!(parse_assignment(&bytes[..len]), None, "length {len}");
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        // Topic name longer than the remaining bytes.
+        let mut bytes = assignment(&[("orders", &[0])]);
+        bytes[6..8].copy_from_slice(&100i16.to_be_bytes());
+        assert_eq!(parse_assignment(&bytes), None);
+        // Negative topic name length.
+        bytes[6..8].copy_from_slice(&(-1i16).to_be_bytes());
+        assert_eq!(parse_assignment(&bytes), None);
+        // Topic name that isn't utf-8.
+        let mut bytes = assignment(&[("orders", &[0])]);
+        bytes[8] = 0xff;
+        assert_eq!(parse_assignment(&bytes), None);
+        // More partitions claimed than present.
+        let mut bytes = assignment(&[("orders", &[0])]);
+        bytes[14..18].copy_from_slice(&i32::MAX.to_be_bytes());
+        assert_eq!(parse_assignment(&bytes), None);
+        // More topics claimed than present.
+        let mut bytes = assignment(&[("orders", &[0])]);
+        bytes[2..6].copy_from_slice(&2i32.to_be_bytes());
+        assert_eq!(parse_assignment(&bytes), None);
+    }
+}
@@ -1,9 +1,24 @@
+use clap::ArgMatches;
 use rdkafka::consumer::{BaseConsumer, Consumer};
-use std::time::Duration;
+use rdkafka::error::KafkaError;
+use rdkafka::message::Message;
+use rdkafka::metadata::MetadataTopic;
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::Offset;
+use regex::Regex;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
 
-use crate::common::Verbosity;
+use crate::client::K12Context;
+use crate::common::{Format, FormatConfig, Verbosity};
 
-pub fn list(consumer: BaseConsumer, timeout: Duration, verbosity: Verbosity) {
+pub fn list<const OAUTH: bool>(
+    consumer: BaseConsumer<K12Context<OAUTH>>,
+    timeout: Duration,
+    format_config: FormatConfig,
+    matches: &ArgMatches<'static>,
+) {
     let metadata = consumer
         .fetch_metadata(None, timeout)
         .unwrap_or_else(|err| {
@@ -11,6 +26,95 @@ pub fn list(consumer: BaseConsumer, timeout: Duration, verbosity: Verbosity) {
             std::process::exit(1);
         });
 
+    let names: Option<Vec<&str>> = matches.values_of("topic").map(Iterator::collect);
+    let pattern = matches.value_of("topic-pattern").map(|pattern| {
+        Regex::new(pattern).unwrap_or_else(|err| {
+            eprintln!("Invalid topic pattern: {}", err);
+            std::process::exit(1);
+        })
+    });
+    let hide_internal = matches.is_present("hide-internal");
+    let topics: Vec<&MetadataTopic> = metadata
+        .topics()
+        .iter()
+        .filter(|topic| {
+            names
+                .as_ref()
+                .is_none_or(|names| names.contains(&topic.name()))
+        })
+        .filter(|topic| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.is_match(topic.name()))
+        })
+        .filter(|topic| !(hide_internal && topic.name().starts_with("__")))
+        .collect();
+
+    let stats =
+        (matches.is_present("watermarks") || matches.is_present("message-times")).then(|| {
+            partition_stats(
+                &consumer,
+                &topics,
+                timeout,
+                matches.is_present("message-times"),
+            )
+        });
+    let stats = stats.unwrap_or_default();
+    let topic_stats: HashMap<&str, TopicStats> = topics
+        .iter()
+        .map(|topic| (topic.name(), TopicStats::new(topic.name(), &stats)))
+        .collect();
+
+    if format_config.format == Format::Json {
+        let json = json!({
+            "cluster": {
+                "broker_count": metadata.brokers().len(),
+                "topic_count": metadata.topics().len(),
+                "orig_broker_name": metadata.orig_broker_name(),
+                "orig_broker_id": metadata.orig_broker_id(),
+            },
+            "brokers": metadata.brokers().iter().map(|broker| json!({
+                "id": broker.id(),
+                "host": broker.host(),
+                "port": broker.port(),
+            })).collect::<Vec<_>>(),
+            "topics": topics.iter().map(|topic| {
+                let mut topic_json = json!({
+                    "name": topic.name(),
+                    "error": topic.error().map(|err| format!("{:?}", err)),
+                    "partitions": topic.partitions().iter().map(|partition| {
+                        let mut partition_json = json!({
+                            "id": partition.id(),
+                            "leader": partition.leader(),
+                            "replicas": partition.replicas(),
+                            "isr": partition.isr(),
+                            "error": partition.error().map(|err| format!("{:?}", err)),
+                        });
+                        if let Some(stat) = stats.get(&(topic.name().to_string(), partition.id())) {
+                            let object = partition_json.as_object_mut().expect("object");
+                            object.insert("low".into(), json!(stat.low));
+                            object.insert("high".into(), json!(stat.high));
+                            object.insert("messages".into(), json!(stat.messages()));
+                            object.insert("first_timestamp".into(), json!(stat.first_timestamp));
+                            object.insert("last_timestamp".into(), json!(stat.last_timestamp));
+                        }
+                        partition_json
+                    }).collect::<Vec<_>>(),
+                });
+                if let Some(stat) = topic_stats.get(topic.name()).filter(|_| !stats.is_empty()) {
+                    let object = topic_json.as_object_mut().expect("object");
+                    object.insert("messages".into(), json!(stat.messages));
+                    object.insert("first_timestamp".into(), json!(stat.first_timestamp));
+                    object.insert("last_timestamp".into(), json!(stat.last_timestamp));
+                }
+                topic_json
+            }).collect::<Vec<_>>(),
+        });
+        println!("{json}");
+        std::process::exit(0);
+    }
+
+    let verbosity = format_config.verbosity;
     if verbosity >= Verbosity::Loud {
         println!("Cluster information:");
         println!("  Broker count: {}", metadata.brokers().len());
@@ -20,12 +124,18 @@ pub fn list(consumer: BaseConsumer, timeout: Duration, verbosity: Verbosity) {
     }
 
     println!("\nTopics:");
-    for topic in metadata.topics() {
+    for topic in topics {
         print!("  {}", topic.name());
 
         if topic.error().is_some() {
             print!(" Err: {:?}", topic.error());
         }
+        if let Some(stat) = topic_stats.get(topic.name()).filter(|_| !stats.is_empty()) {
+            print!("  Messages: {}", stat.messages);
+            if let (Some(first), Some(last)) = (stat.first_timestamp, stat.last_timestamp) {
+                print!("  First: {}  Last: {}", first, last);
+            }
+        }
         println!();
         if verbosity >= Verbosity::Loud {
             for partition in topic.partitions() {
@@ -37,9 +147,193 @@ pub fn list(consumer: BaseConsumer, timeout: Duration, verbosity: Verbosity) {
                     partition.isr(),
                     partition.error()
                 );
+                if let Some(stat) = stats.get(&(topic.name().to_string(), partition.id())) {
+                    print!(
+                        "      Low: {}  High: {}  Messages: {}",
+                        stat.low,
+                        stat.high,
+                        stat.messages()
+                    );
+                    if let (Some(first), Some(last)) = (stat.first_timestamp, stat.last_timestamp) {
+                        print!("  First: {}  Last: {}", first, last);
+                    }
+                    println!();
+                }
             }
         }
     }
 
     std::process::exit(0);
 }
+
+struct PartitionStats {
+    low: i64,
+    high: i64,
+    first_timestamp: Option<i64>,
+    last_timestamp: Option<i64>,
+}
+impl PartitionStats {
+    /// Offsets between the watermarks. Compaction and transaction markers make
+    /// this an upper bound rather than an exact count.
+    fn messages(&self) -> i64 {
+        self.high - self.low
+    }
+}
+
+#[derive(Default)]
+struct TopicStats {
+    messages: i64,
+    first_timestamp: Option<i64>,
+    last_timestamp: Option<i64>,
+}
+impl TopicStats {
+    fn new(topic: &str, stats: &HashMap<(String, i32), PartitionStats>) -> Self {
+        stats.iter().filter(|((name, _), _)| name == topic).fold(
+            TopicStats::default(),
+            |total, (_, stat)| TopicStats {
+                messages: total.messages + stat.messages(),
+                first_timestamp: min_some(total.first_timestamp, stat.first_timestamp),
+                last_timestamp: total.last_timestamp.max(stat.last_timestamp),
+            },
+        )
+    }
+}
+
+fn min_some(a: Option<i64>, b: Option<i64>) -> Option<i64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Fetch watermarks for every partition of `topics`, and with `message_times`
+/// the timestamps of the first and last message still on each partition.
+fn partition_stats<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    topics: &[&MetadataTopic],
+    timeout: Duration,
+    message_times: bool,
+) -> HashMap<(String, i32), PartitionStats> {
+    let mut stats = HashMap::new();
+    for topic in topics {
+        for partition in topic.partitions() {
+            let (low, high) = consumer
+                .fetch_watermarks(topic.name(), partition.id(), timeout)
+                .unwrap_or_else(|err| {
+                    eprintln!(
+                        "error partition watermark {}/{}: {:?}",
+                        topic.name(),
+                        partition.id(),
+                        err
+                    );
+                    std::process::exit(1);
+                });
+            stats.insert(
+                (topic.name().to_string(), partition.id()),
+                PartitionStats {
+                    low,
+                    high,
+                    first_timestamp: None,
+                    last_timestamp: None,
+                },
+            );
+        }
+    }
+    if !message_times {
+        return stats;
+    }
+
+    let non_empty = || stats.iter().filter(|(_, stat)| stat.high > stat.low);
+    let mut first = TopicPartitionList::new();
+    let mut last = TopicPartitionList::new();
+    for ((topic, partition), stat) in non_empty() {
+        first
+            .add_partition_offset(topic, *partition, Offset::Offset(stat.low))
+            .expect("cannot set offset");
+        // One further back, so a partition ending in a transaction marker
+        // still has a message to read.
+        let last_start = (stat.high - 2).max(stat.low);
+        last.add_partition_offset(topic, *partition, Offset::Offset(last_start))
+            .expect("cannot set offset");
+    }
+    let ends = non_empty()
+        .map(|(key, stat)| (key.clone(), stat.high))
+        .collect();
+    let first = message_timestamps(consumer, &first, &ends, false, timeout);
+    let last = message_timestamps(consumer, &last, &ends, true, timeout);
+    for (key, stat) in stats.iter_mut() {
+        stat.first_timestamp = first.get(key).copied();
+        stat.last_timestamp = last.get(key).copied();
+    }
+    stats
+}
+
+/// Read from each position in `tpl` up to the first message, or with
+/// `to_end` up to the partition's end in `ends`, and return the timestamp of
+/// the last message read. Partitions that end without a message that has a
+/// timestamp, or that are still being read at `timeout`, are left out.
+fn message_timestamps<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    tpl: &TopicPartitionList,
+    ends: &HashMap<(String, i32), i64>,
+    to_end: bool,
+    timeout: Duration,
+) -> HashMap<(String, i32), i64> {
+    let mut timestamps = HashMap::with_capacity(tpl.count());
+    if tpl.count() == 0 {
+        return timestamps;
+    }
+    let mut pending: HashSet<(String, i32)> = tpl
+        .elements()
+        .iter()
+        .map(|elem| (elem.topic().to_string(), elem.partition()))
+        .collect();
+    consumer.assign(tpl).unwrap_or_else(|err| {
+        eprintln!("could not assign partitions: {:?}", err);
+        std::process::exit(1);
+    });
+    let deadline = Instant::now() + timeout;
+    while !pending.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            eprintln!("timed out reading message timestamps");
+            break;
+        }
+        match consumer.poll(remaining) {
+            Some(Ok(m)) => {
+                let key = (m.topic().to_string(), m.partition());
+                if !pending.contains(&key) {
+                    continue;
+                }
+                if let Some(timestamp) = m.timestamp().to_millis() {
+                    timestamps.insert(key.clone(), timestamp);
+                }
+                let at_end = ends.get(&key).is_some_and(|end| m.offset() >= end - 1);
+                if !to_end || at_end {
+                    pending.remove(&key);
+                }
+            }
+            // The last offset can be a transaction marker, which is never
+            // delivered, so go by the positions instead.
+            Some(Err(KafkaError::PartitionEOF(_))) => match consumer.position() {
+                Ok(positions) => {
+                    for elem in positions.elements() {
+                        let key = (elem.topic().to_string(), elem.partition());
+                        if let (Offset::Offset(position), Some(end)) =
+                            (elem.offset(), ends.get(&key))
+                        {
+                            if position >= *end {
+                                pending.remove(&key);
+                            }
+                        }
+                    }
+                }
+                Err(err) => eprintln!("error getting positions: {:?}", err),
+            },
+            Some(Err(e)) => eprintln!("Kafka error: {}", e),
+            None => {}
+        }
+    }
+    consumer.unassign().expect("unassign");
+    timestamps
+}
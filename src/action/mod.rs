@@ -1,9 +1,21 @@
+mod config;
+mod context;
+mod groups;
 mod list;
 mod read;
+mod reset;
 mod tail;
+mod topic;
+mod truncate;
 mod write;
 
+pub use config::config;
+pub use context::context;
+pub use groups::groups;
 pub use list::list;
 pub use read::read;
+pub use reset::reset_offsets;
 pub use tail::tail;
-pub use write::produce;
+pub use topic::topic;
+pub use truncate::truncate;
+pub use write::write;
@@ -2,13 +2,18 @@ use chrono::{DateTime, Utc};
 use chrono_english::{parse_date_string, Dialect};
 use clap::ArgMatches;
 use rdkafka::consumer::{BaseConsumer, Consumer};
-use rdkafka::message::Message;
+use rdkafka::error::KafkaError;
+use rdkafka::message::{Message, OwnedMessage};
 use rdkafka::topic_partition_list::TopicPartitionList;
 use rdkafka::util::Timeout;
 use rdkafka::Offset;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::time::Duration;
 
+use crate::client::K12Context;
 use crate::common::{print_message, FormatConfig};
+use crate::filter::Filters;
+use crate::jq::Jq;
 
 fn parse_datetime(datetime_str: &str) -> Result<DateTime<Utc>, String> {
     DateTime::parse_from_rfc3339(datetime_str)
@@ -16,20 +21,46 @@ fn parse_datetime(datetime_str: &str) -> Result<DateTime<Utc>, String> {
         .map_err(|err| format!("{}", err))
 }
 
-pub fn read(
-    consumer: BaseConsumer,
-    format_config: FormatConfig,
+/// An RFC 3339 datetime or a `chrono_english` phrase such as "2 hours ago".
+pub(crate) fn parse_time(time: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    parse_datetime(time)
+        .or_else(|_| parse_date_string(time, now, Dialect::Us).map_err(|err| format!("{}", err)))
+}
+
+/// Resolve every partition in `tpl` to the offset of its first message at or
+/// after `time_millis`. Partitions with nothing that late come back as
+/// `Offset::End`.
+pub(crate) fn offsets_for_time<C: Consumer<K12Context<OAUTH>>, const OAUTH: bool>(
+    consumer: &C,
+    mut tpl: TopicPartitionList,
+    time_millis: i64,
     timeout: Duration,
-    matches: &ArgMatches<'static>,
-) {
-    let topic = matches.value_of("topic").unwrap_or_else(|| {
-        eprintln!("topic is required");
-        std::process::exit(1);
-    });
+) -> TopicPartitionList {
+    tpl.set_all_offsets(Offset::Offset(time_millis))
+        .expect("cannot set time offsets");
 
+    let mut tpl = consumer.offsets_for_times(tpl, timeout).expect("msg");
+    tpl.set_all_offsets(Offset::Offset(time_millis))
+        .expect("cannot set time offsets");
     consumer
-        .subscribe(&[topic])
-        .expect("Can't subscribe to specified topics");
+        .offsets_for_times(tpl, Timeout::Never)
+        .expect("offsets_for_times failed to set")
+}
+
+pub fn read<const OAUTH: bool>(
+    consumer: BaseConsumer<K12Context<OAUTH>>,
+    mut format_config: FormatConfig,
+    timeout: Duration,
+    matches: &ArgMatches<'static>,
+) {
+    let topics: Vec<&str> = matches
+        .values_of("topic")
+        .unwrap_or_else(|| {
+            eprintln!("topic is required");
+            std::process::exit(1);
+        })
+        .collect();
+    format_config.show_topic = topics.len() > 1;
 
     let now = Utc::now();
     let start_datetime = matches.value_of("start").map(|start| {
@@ -100,33 +131,27 @@ pub fn read(
         .expect("end_time should always be set")
         .timestamp_millis();
     let start_time = start_datetime.or(start_offset);
-    let metadata = consumer
-        .fetch_metadata(Some(topic), timeout)
-        .expect("metadata could not be loaded");
-
-    let metadata_topics = metadata.topics();
-    let meta_topic = &metadata_topics[0];
-    let partitions = meta_topic.partitions();
-    if partitions.is_empty() {
-        eprintln!("No partitions found for {topic}.");
-        std::process::exit(1);
-    }
+    let mut tpl = TopicPartitionList::new();
+    for topic in &topics {
+        let metadata = consumer
+            .fetch_metadata(Some(topic), timeout)
+            .expect("metadata could not be loaded");
+
+        let metadata_topics = metadata.topics();
+        let meta_topic = &metadata_topics[0];
+        let partitions = meta_topic.partitions();
+        if partitions.is_empty() {
+            eprintln!("No partitions found for {topic}.");
+            std::process::exit(1);
+        }
 
-    let mut tpl = TopicPartitionList::with_capacity(partitions.len());
-    for partition in partitions {
-        tpl.add_partition(topic, partition.id());
+        for partition in partitions {
+            tpl.add_partition(topic, partition.id());
+        }
     }
 
     let tpl = if let Some(start_time) = start_time {
-        tpl.set_all_offsets(Offset::Offset(start_time.timestamp_millis()))
-            .expect("cannot set time offsets");
-
-        let mut tpl = consumer.offsets_for_times(tpl, timeout).expect("msg");
-        tpl.set_all_offsets(Offset::Offset(start_time.timestamp_millis()))
-            .expect("cannot set time offsets");
-        consumer
-            .offsets_for_times(tpl, Timeout::Never)
-            .expect("offsets_for_times failed to set")
+        offsets_for_time(&consumer, tpl, start_time.timestamp_millis(), timeout)
     } else if let Some(offset) = offset {
         tpl.set_all_offsets(Offset::OffsetTail(offset))
             .expect("set all offsets error");
@@ -135,6 +160,12 @@ pub fn read(
         unreachable!("Offset or start date should have been set! ")
     };
 
+    let mut bounds = partition_bounds(&consumer, &tpl, end_time, timeout);
+    if bounds.values().all(|bound| bound.done) {
+        // nothing in range on any partition.
+        std::process::exit(0);
+    }
+
     consumer.assign(&tpl).expect("msg");
     consumer
         .seek_partitions(tpl, timeout)
@@ -142,34 +173,71 @@ pub fn read(
             eprintln!("error with seek partition: {:?}", err);
             std::process::exit(1);
         });
-    let watermarks = consumer
-        .fetch_watermarks(
-            topic,
-            partitions.first().expect("a partition").id(),
-            timeout,
-        )
-        .unwrap_or_else(|err| {
-            eprintln!("error partition watermark: {:?}", err);
-            std::process::exit(1);
-        });
+    for (topic, partition) in bounds
+        .iter()
+        .filter(|(_, bound)| bound.done)
+        .map(|(k, _)| k)
+    {
+        set_paused(&consumer, topic, *partition, true);
+    }
 
+    let mut sorted = matches.is_present("sorted").then(|| {
+        let capacity = matches
+            .value_of("sorted-buffer")
+            .map(|size| {
+                size.parse::<usize>().unwrap_or_else(|err| {
+                    eprintln!("Invalid sorted buffer size: {}", err);
+                    std::process::exit(1);
+                })
+            })
+            .unwrap_or(1000)
+            .max(1);
+        SortedMerge::new(capacity)
+    });
+
+    let filters = Filters::parse(matches.values_of("filter"));
+    format_config.jq = Jq::from_args(matches.value_of("select"), matches.value_of("transform"));
     let mut message_read = false;
     loop {
         let message = consumer.poll(timeout);
         if let Some(m) = message {
             match m {
+                Err(KafkaError::PartitionEOF(_)) => {
+                    mark_reached_end(&consumer, &mut bounds);
+                    if let Some(sorted) = sorted.as_mut() {
+                        sorted.emit_ready(&consumer, &bounds, &format_config);
+                    }
+                    if bounds.values().all(|bound| bound.done) {
+                        break;
+                    }
+                }
                 Err(e) => eprint!("Kafka error: {}", e),
                 Ok(m) => {
                     message_read = true;
-                    if m.timestamp()
+                    let key = (m.topic().to_string(), m.partition());
+                    let bound = match bounds.get_mut(&key) {
+                        Some(bound) if !bound.done => bound,
+                        _ => continue,
+                    };
+                    let in_time = m
+                        .timestamp()
                         .to_millis()
-                        .map(|t| t > end_time)
-                        .unwrap_or(false)
-                    {
-                        std::process::exit(0);
+                        .map(|t| t <= end_time)
+                        .unwrap_or(true);
+                    if m.offset() < bound.end && in_time && filters.matches(&m) {
+                        match sorted.as_mut() {
+                            Some(sorted) => sorted.push(&consumer, m.detach()),
+                            None => print_message(&m, &format_config),
+                        }
+                    }
+                    if m.offset() >= bound.end - 1 {
+                        bound.done = true;
+                        set_paused(&consumer, m.topic(), m.partition(), true);
+                    }
+                    if let Some(sorted) = sorted.as_mut() {
+                        sorted.emit_ready(&consumer, &bounds, &format_config);
                     }
-                    print_message(&m, &format_config);
-                    if m.offset() >= watermarks.1 - 1 {
+                    if bounds.values().all(|bound| bound.done) {
                         break;
                     }
                 }
@@ -180,8 +248,198 @@ pub fn read(
                 eprintln!("Polling timed out no messages read.");
                 std::process::exit(1);
             }
+            mark_reached_end(&consumer, &mut bounds);
+            if bounds.values().all(|bound| bound.done) {
+                break;
+            }
         }
     }
+    if let Some(sorted) = sorted.as_mut() {
+        sorted.emit_ready(&consumer, &bounds, &format_config);
+    }
     // don't bother with destructors.
     std::process::exit(0);
 }
+
+/// Where a partition stops being read. `end` is exclusive.
+struct PartitionBound {
+    end: i64,
+    done: bool,
+}
+
+/// Resolve the end offset of every partition in `tpl`: the high watermark, or
+/// the first offset past `end_time` if that comes sooner. Partitions whose start
+/// position is already at or past the end are marked done.
+fn partition_bounds<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    tpl: &TopicPartitionList,
+    end_time: i64,
+    timeout: Duration,
+) -> HashMap<(String, i32), PartitionBound> {
+    let mut end_tpl = TopicPartitionList::with_capacity(tpl.count());
+    for elem in tpl.elements() {
+        end_tpl
+            .add_partition_offset(elem.topic(), elem.partition(), Offset::Offset(end_time + 1))
+            .expect("cannot set time offsets");
+    }
+    let end_tpl = consumer
+        .offsets_for_times(end_tpl, timeout)
+        .unwrap_or_else(|err| {
+            eprintln!("error looking up end offsets: {:?}", err);
+            std::process::exit(1);
+        });
+
+    let mut bounds = HashMap::with_capacity(tpl.count());
+    for elem in tpl.elements() {
+        let (topic, partition) = (elem.topic(), elem.partition());
+        let (low, high) = consumer
+            .fetch_watermarks(topic, partition, timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("error partition watermark: {:?}", err);
+                std::process::exit(1);
+            });
+        let end = match end_tpl
+            .find_partition(topic, partition)
+            .map(|elem| elem.offset())
+        {
+            Some(Offset::Offset(end)) => end.min(high),
+            _ => high,
+        };
+        let start = match elem.offset() {
+            Offset::Offset(start) => start,
+            Offset::OffsetTail(tail) => (high - tail).max(low),
+            Offset::Beginning => low,
+            _ => high,
+        };
+        bounds.insert(
+            (topic.to_string(), partition),
+            PartitionBound {
+                end,
+                done: start >= end,
+            },
+        );
+    }
+    bounds
+}
+
+/// Mark partitions whose position has reached their end as done. The last
+/// offset of a transactional partition is a commit marker that is never
+/// delivered, so the last message seen can stop short of the end.
+fn mark_reached_end<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    bounds: &mut HashMap<(String, i32), PartitionBound>,
+) {
+    let positions = match consumer.position() {
+        Ok(positions) => positions,
+        Err(err) => {
+            eprintln!("error getting positions: {:?}", err);
+            return;
+        }
+    };
+    for elem in positions.elements() {
+        let (topic, partition) = (elem.topic(), elem.partition());
+        let Some(bound) = bounds.get_mut(&(topic.to_string(), partition)) else {
+            continue;
+        };
+        if let Offset::Offset(position) = elem.offset() {
+            if !bound.done && position >= bound.end {
+                bound.done = true;
+                set_paused(consumer, topic, partition, true);
+            }
+        }
+    }
+}
+
+fn set_paused<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    topic: &str,
+    partition: i32,
+    paused: bool,
+) {
+    let mut tpl = TopicPartitionList::with_capacity(1);
+    tpl.add_partition(topic, partition);
+    let result = if paused {
+        consumer.pause(&tpl)
+    } else {
+        consumer.resume(&tpl)
+    };
+    if let Err(err) = result {
+        eprintln!("could not pause/resume {topic}/{partition}: {:?}", err);
+    }
+}
+
+/// Buffers messages per partition and emits them as a k-way merge ordered by
+/// timestamp, then topic, partition and offset. A message is only emitted once
+/// every partition that is still being read has something buffered, so nothing
+/// earlier can arrive later. Partitions holding `capacity` messages are paused
+/// until the merge drains them.
+struct SortedMerge {
+    buffers: HashMap<(String, i32), VecDeque<OwnedMessage>>,
+    full: HashSet<(String, i32)>,
+    capacity: usize,
+}
+
+impl SortedMerge {
+    fn new(capacity: usize) -> Self {
+        SortedMerge {
+            buffers: HashMap::new(),
+            full: HashSet::new(),
+            capacity,
+        }
+    }
+
+    fn push<const OAUTH: bool>(
+        &mut self,
+        consumer: &BaseConsumer<K12Context<OAUTH>>,
+        m: OwnedMessage,
+    ) {
+        let key = (m.topic().to_string(), m.partition());
+        let buffer = self.buffers.entry(key.clone()).or_default();
+        buffer.push_back(m);
+        if buffer.len() >= self.capacity && !self.full.contains(&key) {
+            set_paused(consumer, &key.0, key.1, true);
+            self.full.insert(key);
+        }
+    }
+
+    fn emit_ready<const OAUTH: bool>(
+        &mut self,
+        consumer: &BaseConsumer<K12Context<OAUTH>>,
+        bounds: &HashMap<(String, i32), PartitionBound>,
+        format_config: &FormatConfig,
+    ) {
+        loop {
+            let waiting = bounds.iter().any(|(key, bound)| {
+                !bound.done && self.buffers.get(key).is_none_or(VecDeque::is_empty)
+            });
+            if waiting {
+                return;
+            }
+            let next = self
+                .buffers
+                .iter()
+                .filter_map(|(key, buffer)| buffer.front().map(|m| (merge_order(m), key)))
+                .min()
+                .map(|(_, key)| key.clone());
+            let Some(key) = next else {
+                return;
+            };
+            let buffer = self.buffers.get_mut(&key).expect("buffer for key");
+            let m = buffer.pop_front().expect("buffered message");
+            print_message(&m, format_config);
+            let done = bounds.get(&key).is_none_or(|bound| bound.done);
+            if buffer.len() < self.capacity && self.full.remove(&key) && !done {
+                set_paused(consumer, &key.0, key.1, false);
+            }
+        }
+    }
+}
+
+fn merge_order(m: &OwnedMessage) -> (i64, &str, i32, i64) {
+    (
+        m.timestamp().to_millis().unwrap_or_default(),
+        m.topic(),
+        m.partition(),
+        m.offset(),
+    )
+}
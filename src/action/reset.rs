@@ -0,0 +1,249 @@
+use crate::client::{K12Context, KafkaConfig};
+use chrono::Utc;
+use clap::ArgMatches;
+use rdkafka::consumer::{CommitMode, Consumer};
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::Offset;
+use serde_json::json;
+use std::time::Duration;
+
+use super::groups::create_consumer;
+use super::read::{offsets_for_time, parse_time};
+use crate::common::{Format, FormatConfig};
+
+/// Where to move the committed offsets to.
+enum Target {
+    Earliest,
+    Latest,
+    Offset(i64),
+    Shift(i64),
+    Time(i64),
+}
+
+struct Change {
+    topic: String,
+    partition: i32,
+    current: Option<i64>,
+    new: i64,
+}
+
+pub fn reset_offsets<const OAUTH: bool>(
+    config: KafkaConfig,
+    timeout: Duration,
+    format_config: FormatConfig,
+    matches: &ArgMatches<'static>,
+) {
+    let group = matches.value_of("name").expect("group is required");
+    let dry_run = matches.is_present("dry-run");
+    let target = target(matches);
+
+    let consumer = create_consumer::<OAUTH>(&config);
+    let group_list = consumer
+        .fetch_group_list(Some(group), timeout)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to describe group {}: {}", group, err);
+            std::process::exit(1);
+        });
+    let active_members = group_list
+        .groups()
+        .first()
+        .map(|group| group.members().len())
+        .unwrap_or_default();
+
+    let tpl = partitions(&consumer, matches, timeout);
+    let group_consumer = create_consumer::<OAUTH>(config.clone().set("group.id", group));
+    let committed = group_consumer
+        .committed_offsets(tpl.clone(), timeout)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to fetch committed offsets for {}: {}", group, err);
+            std::process::exit(1);
+        });
+    let times = match target {
+        Target::Time(time) => Some(offsets_for_time(&consumer, tpl.clone(), time, timeout)),
+        _ => None,
+    };
+
+    let mut changes = Vec::with_capacity(tpl.count());
+    for elem in tpl.elements() {
+        let (topic, partition) = (elem.topic(), elem.partition());
+        let (low, high) = consumer
+            .fetch_watermarks(topic, partition, timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("error partition watermark {topic}/{partition}: {:?}", err);
+                std::process::exit(1);
+            });
+        let current = match committed
+            .find_partition(topic, partition)
+            .map(|e| e.offset())
+        {
+            Some(Offset::Offset(offset)) => Some(offset),
+            _ => None,
+        };
+        let new = match target {
+            Target::Earliest => low,
+            Target::Latest => high,
+            Target::Offset(offset) => offset,
+            Target::Shift(shift) => match current {
+                Some(current) => current + shift,
+                None => {
+                    eprintln!("{topic}/{partition} has no committed offset to shift from.");
+                    std::process::exit(1);
+                }
+            },
+            Target::Time(_) => match times
+                .as_ref()
+                .and_then(|times| times.find_partition(topic, partition))
+                .map(|e| e.offset())
+            {
+                Some(Offset::Offset(offset)) => offset,
+                _ => high,
+            },
+        };
+        changes.push(Change {
+            topic: topic.to_string(),
+            partition,
+            current,
+            new: new.clamp(low, high),
+        });
+    }
+
+    print_plan(group, &changes, dry_run, &format_config);
+    if active_members > 0 {
+        eprintln!(
+            "Group {group} has {active_members} active members. Stop them before resetting offsets."
+        );
+        std::process::exit(1);
+    }
+    if dry_run {
+        std::process::exit(0);
+    }
+
+    let mut new_offsets = TopicPartitionList::with_capacity(changes.len());
+    for change in &changes {
+        new_offsets
+            .add_partition_offset(&change.topic, change.partition, Offset::Offset(change.new))
+            .expect("cannot set offset");
+    }
+    group_consumer
+        .commit(&new_offsets, CommitMode::Sync)
+        .unwrap_or_else(|err| {
+            eprintln!("Failed to commit offsets for {}: {}", group, err);
+            std::process::exit(1);
+        });
+    std::process::exit(0);
+}
+
+fn target(matches: &ArgMatches<'static>) -> Target {
+    let number = |name: &str| {
+        matches.value_of(name).map(|value| {
+            value.parse::<i64>().unwrap_or_else(|err| {
+                eprintln!("Invalid {name}: {}", err);
+                std::process::exit(1);
+            })
+        })
+    };
+    if matches.is_present("to-earliest") {
+        Target::Earliest
+    } else if matches.is_present("to-latest") {
+        Target::Latest
+    } else if let Some(offset) = number("to-offset") {
+        Target::Offset(offset)
+    } else if let Some(shift) = number("shift-by") {
+        Target::Shift(shift)
+    } else if let Some(time) = matches.value_of("to-time") {
+        let time = parse_time(time, Utc::now()).unwrap_or_else(|err| {
+            eprintln!("Invalid time: {}", err);
+            std::process::exit(1);
+        });
+        Target::Time(time.timestamp_millis())
+    } else {
+        unreachable!("clap requires one reset target")
+    }
+}
+
+/// The partitions named by `--topic`, either `topic` for all of its partitions
+/// or `topic:0,2` for some of them.
+pub(crate) fn partitions<C: Consumer<K12Context<OAUTH>>, const OAUTH: bool>(
+    consumer: &C,
+    matches: &ArgMatches<'static>,
+    timeout: Duration,
+) -> TopicPartitionList {
+    let mut tpl = TopicPartitionList::new();
+    for spec in matches.values_of("topic").expect("topic is required") {
+        let (topic, partitions) = match spec.split_once(':') {
+            Some((topic, partitions)) => {
+                let partitions = partitions
+                    .split(',')
+                    .map(|partition| {
+                        partition.trim().parse::<i32>().unwrap_or_else(|err| {
+                            eprintln!("Invalid partition in {spec}: {}", err);
+                            std::process::exit(1);
+                        })
+                    })
+                    .collect();
+                (topic, Some(partitions))
+            }
+            None => (spec, None),
+        };
+        let metadata = consumer
+            .fetch_metadata(Some(topic), timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("Failed to fetch metadata for {}: {}", topic, err);
+                std::process::exit(1);
+            });
+        let known: Vec<i32> = metadata
+            .topics()
+            .first()
+            .map(|topic| topic.partitions().iter().map(|p| p.id()).collect())
+            .unwrap_or_default();
+        if known.is_empty() {
+            eprintln!("No partitions found for {topic}.");
+            std::process::exit(1);
+        }
+        let partitions: Vec<i32> = partitions.unwrap_or_else(|| known.clone());
+        for partition in partitions {
+            if !known.contains(&partition) {
+                eprintln!("{topic} has no partition {partition}.");
+                std::process::exit(1);
+            }
+            tpl.add_partition(topic, partition);
+        }
+    }
+    tpl
+}
+
+fn print_plan(group: &str, changes: &[Change], dry_run: bool, format_config: &FormatConfig) {
+    match format_config.format {
+        Format::Json => {
+            let json = json!({
+                "group": group,
+                "dry_run": dry_run,
+                "partitions": changes.iter().map(|change| json!({
+                    "topic": change.topic,
+                    "partition": change.partition,
+                    "current": change.current,
+                    "new": change.new,
+                })).collect::<Vec<_>>(),
+            });
+            println!("{json}");
+        }
+        Format::Raw => {
+            match dry_run {
+                true => println!("Group: {group} (dry run, nothing committed)"),
+                false => println!("Group: {group}"),
+            }
+            for change in changes {
+                println!(
+                    "  {}  Partition: {}  Current: {}  New: {}",
+                    change.topic,
+                    change.partition,
+                    change
+                        .current
+                        .map(|offset| offset.to_string())
+                        .unwrap_or_else(|| "-".to_string()),
+                    change.new
+                );
+            }
+        }
+    }
+}
@@ -1,18 +1,114 @@
+use chrono::Utc;
+use clap::ArgMatches;
 use rdkafka::consumer::stream_consumer::StreamConsumer;
 use rdkafka::consumer::{CommitMode, Consumer};
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::Offset;
+use std::time::Duration;
 
+use super::read::{offsets_for_time, parse_time};
+use super::reset::partitions;
+use crate::client::K12Context;
 use crate::common::{print_message, FormatConfig};
-pub async fn tail(consumer: StreamConsumer, format_config: FormatConfig, topics: Vec<&str>) {
-    consumer
-        .subscribe(&topics.to_vec())
-        .expect("Can't subscribe to specified topics");
+use crate::filter::Filters;
+use crate::jq::Jq;
+
+pub async fn tail<const OAUTH: bool>(
+    consumer: StreamConsumer<K12Context<OAUTH>>,
+    mut format_config: FormatConfig,
+    timeout: Duration,
+    matches: &ArgMatches<'static>,
+) {
+    let commit = matches.is_present("commit-group");
+    if let Some(pattern) = matches.value_of("topic-pattern") {
+        // librdkafka treats a topic starting with ^ as a regex.
+        let pattern = match pattern.starts_with('^') {
+            true => pattern.to_string(),
+            false => format!("^{pattern}"),
+        };
+        consumer
+            .subscribe(&[&pattern])
+            .expect("Can't subscribe to topic pattern");
+    } else {
+        match start_offsets(&consumer, commit, timeout, matches) {
+            Some(tpl) => consumer
+                .assign(&tpl)
+                .expect("Can't assign specified partitions"),
+            None => {
+                let topics: Vec<&str> = matches.values_of("topic").expect("topic").collect();
+                consumer
+                    .subscribe(&topics)
+                    .expect("Can't subscribe to specified topics")
+            }
+        }
+    }
+    let filters = Filters::parse(matches.values_of("filter"));
+    format_config.jq = Jq::from_args(matches.value_of("select"), matches.value_of("transform"));
     loop {
         match consumer.recv().await {
             Err(e) => eprint!("Kafka error: {}", e),
             Ok(m) => {
-                print_message(&m, &format_config);
-                consumer.commit_message(&m, CommitMode::Async).unwrap();
+                if filters.matches(&m) {
+                    print_message(&m, &format_config);
+                }
+                if commit {
+                    if let Err(err) = consumer.commit_message(&m, CommitMode::Async) {
+                        eprintln!("Could not commit: {}", err);
+                    }
+                }
             }
         };
     }
 }
+
+/// The partitions to assign and where to start in each of them. Only when
+/// committing under a group without a start position does tail subscribe
+/// instead, picking up from the group's committed offsets. Partitions named
+/// with `topic:0,2` can't be subscribed to, so those are assigned at the
+/// group's offsets. Otherwise it starts at the end, leaving no committed
+/// offsets behind.
+fn start_offsets<const OAUTH: bool>(
+    consumer: &StreamConsumer<K12Context<OAUTH>>,
+    commit: bool,
+    timeout: Duration,
+    matches: &ArgMatches<'static>,
+) -> Option<TopicPartitionList> {
+    let named_partitions = matches
+        .values_of("topic")
+        .is_some_and(|mut specs| specs.any(|spec| spec.contains(':')));
+    if commit && !matches.is_present("start") && !named_partitions {
+        return None;
+    }
+    let mut tpl = partitions(consumer, matches, timeout);
+    if commit && !matches.is_present("start") {
+        tpl.set_all_offsets(Offset::Stored)
+            .expect("cannot set start offsets");
+        return Some(tpl);
+    }
+    if let Some(since) = matches.value_of("since") {
+        let time = parse_time(since, Utc::now()).unwrap_or_else(|err| {
+            eprintln!("Invalid time: {}", err);
+            std::process::exit(1);
+        });
+        return Some(offsets_for_time(
+            consumer,
+            tpl,
+            time.timestamp_millis(),
+            timeout,
+        ));
+    }
+    let offset = if matches.is_present("from-beginning") {
+        Offset::Beginning
+    } else if let Some(count) = matches.value_of("offset-tail") {
+        let count = count.parse::<u32>().unwrap_or_else(|err| {
+            eprintln!("Invalid offset-tail: {}", err);
+            std::process::exit(1);
+        });
+        Offset::OffsetTail(count.into())
+    } else {
+        Offset::End
+    };
+    tpl.set_all_offsets(offset)
+        .expect("cannot set start offsets");
+    Some(tpl)
+}
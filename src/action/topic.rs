@@ -0,0 +1,111 @@
+use crate::client::{K12Context, KafkaConfig};
+use clap::ArgMatches;
+use rdkafka::admin::{
+    AdminClient, AdminOptions, NewPartitions, NewTopic, TopicReplication, TopicResult,
+};
+use std::io::BufRead;
+use std::time::Duration;
+
+pub async fn topic<const OAUTH: bool>(
+    config: KafkaConfig,
+    timeout: Duration,
+    matches: &ArgMatches<'static>,
+) {
+    let admin: AdminClient<K12Context<OAUTH>> = config.create().unwrap_or_else(|err| {
+        eprintln!("Could not create admin client: {}", err);
+        std::process::exit(1);
+    });
+    let opts = AdminOptions::new().operation_timeout(Some(timeout));
+
+    let results = match matches.subcommand() {
+        ("create", Some(matches)) => {
+            let name = matches.value_of("name").expect("topic name is required");
+            let partitions = number(matches, "partitions").unwrap_or(1);
+            let replication = number(matches, "replication-factor").unwrap_or(1);
+            let configs: Vec<(&str, &str)> = matches
+                .values_of("config")
+                .map(|configs| configs.map(split_config).collect())
+                .unwrap_or_default();
+            let new_topic = configs.iter().fold(
+                NewTopic::new(name, partitions, TopicReplication::Fixed(replication)),
+                |new_topic, (key, value)| new_topic.set(key, value),
+            );
+            admin.create_topics([&new_topic], &opts).await
+        }
+        ("delete", Some(matches)) => {
+            let names: Vec<&str> = matches
+                .values_of("name")
+                .expect("topic name is required")
+                .collect();
+            if !matches.is_present("yes") && !confirm_delete(&names) {
+                eprintln!("Not deleting.");
+                std::process::exit(1);
+            }
+            admin.delete_topics(&names, &opts).await
+        }
+        ("add-partitions", Some(matches)) => {
+            let name = matches.value_of("name").expect("topic name is required");
+            let count = number(matches, "count").expect("count is required");
+            let new_partitions = NewPartitions::new(name, count as usize);
+            admin.create_partitions([&new_partitions], &opts).await
+        }
+        _ => unreachable!(),
+    };
+    report(results);
+}
+
+fn number(matches: &ArgMatches<'_>, name: &str) -> Option<i32> {
+    matches.value_of(name).map(|value| {
+        value
+            .parse::<i32>()
+            .ok()
+            .filter(|value| *value > 0)
+            .unwrap_or_else(|| {
+                eprintln!("Invalid {name}: {value}");
+                std::process::exit(1);
+            })
+    })
+}
+
+/// Split a `key=value` config override.
+pub(crate) fn split_config(config: &str) -> (&str, &str) {
+    config.split_once('=').unwrap_or_else(|| {
+        eprintln!("Invalid config {config}, expected key=value");
+        std::process::exit(1);
+    })
+}
+
+/// Ask for the topic names to be typed back before deleting them.
+fn confirm_delete(names: &[&str]) -> bool {
+    eprintln!(
+        "About to delete {}. Type the topic name(s) separated by spaces to confirm:",
+        names.join(", ")
+    );
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    let mut typed: Vec<&str> = answer.split_whitespace().collect();
+    let mut expected = names.to_vec();
+    typed.sort_unstable();
+    expected.sort_unstable();
+    typed == expected
+}
+
+fn report(results: rdkafka::error::KafkaResult<Vec<TopicResult>>) {
+    let results = results.unwrap_or_else(|err| {
+        eprintln!("Admin request failed: {}", err);
+        std::process::exit(1);
+    });
+    let mut failed = false;
+    for result in results {
+        match result {
+            Ok(topic) => println!("{topic}: ok"),
+            Err((topic, err)) => {
+                failed = true;
+                eprintln!("{topic}: {err}");
+            }
+        }
+    }
+    std::process::exit(if failed { 1 } else { 0 });
+}
@@ -0,0 +1,206 @@
+use chrono::Utc;
+use clap::ArgMatches;
+use rdkafka::bindings as rdsys;
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::RDKafkaErrorCode;
+use rdkafka::topic_partition_list::TopicPartitionList;
+use rdkafka::Offset;
+use serde_json::json;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::time::Duration;
+
+use super::read::{offsets_for_time, parse_time};
+use super::reset::partitions;
+use crate::client::K12Context;
+use crate::common::{Format, FormatConfig};
+
+struct Truncation {
+    topic: String,
+    partition: i32,
+    low: i64,
+    new_low: i64,
+}
+
+pub fn truncate<const OAUTH: bool>(
+    consumer: BaseConsumer<K12Context<OAUTH>>,
+    timeout: Duration,
+    format_config: FormatConfig,
+    matches: &ArgMatches<'static>,
+) {
+    let dry_run = matches.is_present("dry-run");
+    let tpl = partitions(&consumer, matches, timeout);
+    let before_offset = matches.value_of("before-offset").map(|offset| {
+        offset.parse::<i64>().unwrap_or_else(|err| {
+            eprintln!("Invalid offset: {}", err);
+            std::process::exit(1);
+        })
+    });
+    let times = matches.value_of("before-time").map(|time| {
+        let time = parse_time(time, Utc::now()).unwrap_or_else(|err| {
+            eprintln!("Invalid time: {}", err);
+            std::process::exit(1);
+        });
+        offsets_for_time(&consumer, tpl.clone(), time.timestamp_millis(), timeout)
+    });
+
+    let mut truncations = Vec::with_capacity(tpl.count());
+    for elem in tpl.elements() {
+        let (topic, partition) = (elem.topic(), elem.partition());
+        let (low, high) = consumer
+            .fetch_watermarks(topic, partition, timeout)
+            .unwrap_or_else(|err| {
+                eprintln!("error partition watermark {topic}/{partition}: {:?}", err);
+                std::process::exit(1);
+            });
+        let new_low = match (before_offset, &times) {
+            (Some(offset), _) => offset,
+            (None, Some(times)) => match times
+                .find_partition(topic, partition)
+                .map(|elem| elem.offset())
+            {
+                Some(Offset::Offset(offset)) => offset,
+                _ => high,
+            },
+            (None, None) => unreachable!("clap requires --before-offset or --before-time"),
+        };
+        truncations.push(Truncation {
+            topic: topic.to_string(),
+            partition,
+            low,
+            new_low: new_low.clamp(low, high),
+        });
+    }
+
+    print_plan(&truncations, dry_run, &format_config);
+    if dry_run {
+        std::process::exit(0);
+    }
+
+    let mut before = TopicPartitionList::with_capacity(truncations.len());
+    for truncation in truncations.iter().filter(|t| t.new_low > t.low) {
+        before
+            .add_partition_offset(
+                &truncation.topic,
+                truncation.partition,
+                Offset::Offset(truncation.new_low),
+            )
+            .expect("cannot set offset");
+    }
+    if before.count() == 0 {
+        std::process::exit(0);
+    }
+
+    let mut failed = false;
+    for (topic, partition, result) in delete_records(&consumer, &before, timeout) {
+        match result {
+            Ok(low) => println!("{topic}/{partition}: low watermark now {low}"),
+            Err(err) => {
+                failed = true;
+                eprintln!("{topic}/{partition}: {err}");
+            }
+        }
+    }
+    std::process::exit(if failed { 1 } else { 0 });
+}
+
+fn print_plan(truncations: &[Truncation], dry_run: bool, format_config: &FormatConfig) {
+    match format_config.format {
+        Format::Json => {
+            let json = json!({
+                "dry_run": dry_run,
+                "partitions": truncations.iter().map(|truncation| json!({
+                    "topic": truncation.topic,
+                    "partition": truncation.partition,
+                    "low": truncation.low,
+                    "new_low": truncation.new_low,
+                    "deleted": truncation.new_low - truncation.low,
+                })).collect::<Vec<_>>(),
+            });
+            println!("{json}");
+        }
+        Format::Raw => {
+            if dry_run {
+                println!("Dry run, nothing deleted");
+            }
+            for truncation in truncations {
+                println!(
+                    "  {}  Partition: {}  Low: {}  New low: {}  Deleted: {}",
+                    truncation.topic,
+                    truncation.partition,
+                    truncation.low,
+                    truncation.new_low,
+                    truncation.new_low - truncation.low
+                );
+            }
+        }
+    }
+}
+
+/// Delete every record before the offsets in `before`. rdkafka has no wrapper
+/// for DeleteRecords yet, so this drives the librdkafka admin call directly on
+/// the consumer's handle and waits on a private queue for the result. Returns
+/// the new low watermark or the error for each partition.
+fn delete_records<const OAUTH: bool>(
+    consumer: &BaseConsumer<K12Context<OAUTH>>,
+    before: &TopicPartitionList,
+    timeout: Duration,
+) -> Vec<(String, i32, Result<i64, String>)> {
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    unsafe {
+        let rk = consumer.client().native_ptr();
+        let queue = rdsys::rd_kafka_queue_new(rk);
+        let options = rdsys::rd_kafka_AdminOptions_new(
+            rk,
+            rdsys::rd_kafka_admin_op_t::RD_KAFKA_ADMIN_OP_DELETERECORDS,
+        );
+        let mut errstr = [0 as c_char; 512];
+        rdsys::rd_kafka_AdminOptions_set_operation_timeout(
+            options,
+            timeout_ms,
+            errstr.as_mut_ptr(),
+            errstr.len(),
+        );
+        // librdkafka copies its arguments, so they can be freed right away.
+        let mut delete = rdsys::rd_kafka_DeleteRecords_new(before.ptr());
+        rdsys::rd_kafka_DeleteRecords(rk, &mut delete, 1, options, queue);
+        rdsys::rd_kafka_DeleteRecords_destroy(delete);
+        rdsys::rd_kafka_AdminOptions_destroy(options);
+
+        let event = rdsys::rd_kafka_queue_poll(queue, timeout_ms.saturating_mul(2));
+        if event.is_null() {
+            rdsys::rd_kafka_queue_destroy(queue);
+            eprintln!("Timed out waiting for delete records result.");
+            std::process::exit(1);
+        }
+        let err = rdsys::rd_kafka_event_error(event);
+        if err != rdsys::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR_NO_ERROR {
+            let message = CStr::from_ptr(rdsys::rd_kafka_event_error_string(event))
+                .to_string_lossy()
+                .into_owned();
+            rdsys::rd_kafka_event_destroy(event);
+            rdsys::rd_kafka_queue_destroy(queue);
+            eprintln!("Delete records failed: {message}");
+            std::process::exit(1);
+        }
+
+        let result = rdsys::rd_kafka_event_DeleteRecords_result(event);
+        let offsets = rdsys::rd_kafka_DeleteRecords_result_offsets(result);
+        let mut results = Vec::new();
+        if !offsets.is_null() {
+            let offsets = &*offsets;
+            for idx in 0..offsets.cnt.max(0) as usize {
+                let elem = &*offsets.elems.add(idx);
+                let topic = CStr::from_ptr(elem.topic).to_string_lossy().into_owned();
+                let result = match elem.err {
+                    rdsys::rd_kafka_resp_err_t::RD_KAFKA_RESP_ERR_NO_ERROR => Ok(elem.offset),
+                    err => Err(RDKafkaErrorCode::from(err).to_string()),
+                };
+                results.push((topic, elem.partition, result));
+            }
+        }
+        rdsys::rd_kafka_event_destroy(event);
+        rdsys::rd_kafka_queue_destroy(queue);
+        results
+    }
+}
@@ -1,14 +1,442 @@
+use chrono::DateTime;
+use clap::ArgMatches;
+use rdkafka::message::{Header, OwnedHeaders};
 use rdkafka::producer::{FutureProducer, FutureRecord};
-use std::{process::exit, time::Duration};
+use rdkafka::util::Timeout;
+use serde_json::Value;
+use std::process::exit;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::task::JoinSet;
 
-pub async fn produce(producer: FutureProducer, topic_name: &str, message: &str) {
-    let record: FutureRecord<'_, str, str> = FutureRecord::to(topic_name).payload(message);
-    let delivery_status = producer.send(record, Duration::from_secs(0)).await;
-    match delivery_status {
-        Ok(_) => {}
-        Err(e) => {
-            eprintln!("Could not write message:{:?}, error:{}", e.1, e.0);
-            exit(1)
+use crate::client::K12Context;
+use crate::common::Encoding;
+
+/// Header name and value pairs, in the order they are sent.
+type RecordHeaders = Vec<(String, Option<Vec<u8>>)>;
+
+/// One message to produce. Unset fields fall back to the command line flags.
+#[derive(Debug, Default, Clone)]
+struct Record {
+    topic: Option<String>,
+    key: Option<Vec<u8>>,
+    payload: Option<Vec<u8>>,
+    headers: RecordHeaders,
+    partition: Option<i32>,
+    timestamp: Option<i64>,
+}
+
+impl Record {
+    /// Fill fields this record does not set from `defaults`. Default headers
+    /// are sent after the record's own. A default topic always wins so
+    /// `--topic` can redirect a replay.
+    fn or(mut self, defaults: &Record) -> Record {
+        self.topic = defaults.topic.clone().or(self.topic);
+        self.key = self.key.or_else(|| defaults.key.clone());
+        self.payload = self.payload.or_else(|| defaults.payload.clone());
+        self.headers.extend(defaults.headers.iter().cloned());
+        self.partition = self.partition.or(defaults.partition);
+        self.timestamp = self.timestamp.or(defaults.timestamp);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputFormat {
+    Raw,
+    Json,
+    /// What `read` and `tail` print with the json format.
+    K12Json,
+}
+
+pub async fn write<const OAUTH: bool>(
+    producer: FutureProducer<K12Context<OAUTH>>,
+    topic: Option<&str>,
+    payload_encoding: Encoding,
+    key_encoding: Encoding,
+    header_encoding: Encoding,
+    matches: &ArgMatches<'static>,
+) {
+    let input_format = match matches.value_of("input-format") {
+        Some("json") => InputFormat::Json,
+        Some("k12-json") => InputFormat::K12Json,
+        _ => InputFormat::Raw,
+    };
+    if topic.is_none() && input_format != InputFormat::K12Json {
+        eprintln!("topic is required");
+        exit(1);
+    }
+    let delimiter = matches
+        .value_of("delimiter")
+        .map(|delimiter| {
+            parse_delimiter(delimiter).unwrap_or_else(|| {
+                eprintln!("Invalid delimiter: {delimiter}. Use a single byte, \\n, \\t or \\0");
+                exit(1);
+            })
+        })
+        .or_else(|| {
+            (matches.is_present("lines") || input_format != InputFormat::Raw).then_some(b'\n')
+        });
+    let mut defaults = record_defaults(matches, key_encoding, header_encoding);
+    defaults.topic = topic.map(ToString::to_string);
+
+    match delimiter {
+        Some(delimiter) => {
+            let max_in_flight = matches
+                .value_of("max-in-flight")
+                .map(|max| {
+                    max.parse::<usize>().unwrap_or_else(|err| {
+                        eprintln!("Invalid max in flight: {}", err);
+                        exit(1);
+                    })
+                })
+                .unwrap_or(1000)
+                .max(1);
+            let stream = Stream {
+                input_format,
+                payload_encoding,
+                key_encoding,
+                header_encoding,
+                delimiter,
+                max_in_flight,
+                keep_partition: matches.is_present("keep-partition"),
+            };
+            produce_stream(producer, defaults, stream).await;
+        }
+        None => {
+            let mut buf = Vec::new();
+            tokio::io::stdin()
+                .read_to_end(&mut buf)
+                .await
+                .expect("could not read stdin");
+            let buf = match payload_encoding.is_text() {
+                true => buf.strip_suffix(b"\n").unwrap_or(&buf),
+                false => &buf,
+            };
+            let payload = payload_encoding.decode(buf).unwrap_or_else(|err| {
+                eprintln!("could not decode stdin as {:?}: {}", payload_encoding, err);
+                exit(1);
+            });
+            let record = Record {
+                payload: Some(payload),
+                ..Default::default()
+            }
+            .or(&defaults);
+            if let Err(err) = send(&producer, &record).await {
+                eprintln!("Could not write message: {err}");
+                exit(1)
+            }
+        }
+    }
+}
+
+/// The `--key`, `--header`, `--partition` and `--timestamp` flags as a record
+/// every message falls back to.
+fn record_defaults(
+    matches: &ArgMatches<'static>,
+    key_encoding: Encoding,
+    header_encoding: Encoding,
+) -> Record {
+    let key = matches.value_of("key").map(|key| {
+        key_encoding.decode(key.as_bytes()).unwrap_or_else(|err| {
+            eprintln!("could not decode key as {:?}: {}", key_encoding, err);
+            exit(1);
+        })
+    });
+    let headers = matches
+        .values_of("header")
+        .map(|headers| {
+            headers
+                .map(|header| match header.split_once('=') {
+                    Some((name, value)) => {
+                        let value =
+                            header_encoding
+                                .decode(value.as_bytes())
+                                .unwrap_or_else(|err| {
+                                    eprintln!(
+                                        "could not decode header {name} as {:?}: {}",
+                                        header_encoding, err
+                                    );
+                                    exit(1);
+                                });
+                        (name.to_string(), Some(value))
+                    }
+                    None => (header.to_string(), None),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let partition = matches.value_of("partition").map(|partition| {
+        partition.parse::<i32>().unwrap_or_else(|err| {
+            eprintln!("Invalid partition: {}", err);
+            exit(1);
+        })
+    });
+    let timestamp = matches.value_of("timestamp").map(|timestamp| {
+        parse_timestamp(timestamp).unwrap_or_else(|err| {
+            eprintln!("Invalid timestamp: {}", err);
+            exit(1);
+        })
+    });
+    Record {
+        topic: None,
+        key,
+        payload: None,
+        headers,
+        partition,
+        timestamp,
+    }
+}
+
+async fn send<const OAUTH: bool>(
+    producer: &FutureProducer<K12Context<OAUTH>>,
+    record: &Record,
+) -> Result<(), String> {
+    let topic_name = record.topic.as_deref().ok_or("no topic to write to")?;
+    let mut future_record: FutureRecord<'_, [u8], [u8]> = FutureRecord::to(topic_name);
+    if let Some(key) = &record.key {
+        future_record = future_record.key(key);
+    }
+    if let Some(payload) = &record.payload {
+        future_record = future_record.payload(payload);
+    }
+    if let Some(partition) = record.partition {
+        future_record = future_record.partition(partition);
+    }
+    if let Some(timestamp) = record.timestamp {
+        future_record = future_record.timestamp(timestamp);
+    }
+    if !record.headers.is_empty() {
+        let headers = record
+            .headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: value.as_deref(),
+                })
+            });
+        future_record = future_record.headers(headers);
+    }
+    // Wait for room in librdkafka's queue rather than fail on a full one, so
+    // only --max-in-flight throttles; message.timeout.ms bounds delivery.
+    producer
+        .send(future_record, Timeout::Never)
+        .await
+        .map(|_| ())
+        .map_err(|(err, _)| format!("{err}"))
+}
+
+struct Stream {
+    input_format: InputFormat,
+    payload_encoding: Encoding,
+    key_encoding: Encoding,
+    header_encoding: Encoding,
+    delimiter: u8,
+    max_in_flight: usize,
+    /// Keep a record's partition when `--topic` sends it to another topic.
+    keep_partition: bool,
+}
+
+/// Send every delimiter separated record on stdin as its own message, keeping
+/// at most `max_in_flight` deliveries outstanding. Empty records are skipped.
+async fn produce_stream<const OAUTH: bool>(
+    producer: FutureProducer<K12Context<OAUTH>>,
+    defaults: Record,
+    stream: Stream,
+) {
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    let mut in_flight = JoinSet::new();
+    let mut record_number = 0usize;
+    let (mut delivered, mut failed) = (0usize, 0usize);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let read = stdin
+            .read_until(stream.delimiter, &mut buf)
+            .await
+            .expect("could not read stdin");
+        if read == 0 {
+            break;
+        }
+        let input = buf.strip_suffix(&[stream.delimiter]).unwrap_or(&buf);
+        if input.is_empty() {
+            continue;
+        }
+        record_number += 1;
+        let record = match stream.input_format {
+            InputFormat::Raw => stream.payload_encoding.decode(input).map(|payload| Record {
+                payload: Some(payload),
+                ..Default::default()
+            }),
+            InputFormat::Json | InputFormat::K12Json => parse_json_record(
+                input,
+                stream.input_format,
+                stream.payload_encoding,
+                stream.key_encoding,
+                stream.header_encoding,
+            ),
+        };
+        let record = match record {
+            Ok(mut record) => {
+                // A partition read from one topic means nothing on another.
+                let redirected = defaults.topic.is_some()
+                    && record.topic.is_some()
+                    && record.topic != defaults.topic;
+                if redirected && !stream.keep_partition {
+                    record.partition = None;
+                }
+                record.or(&defaults)
+            }
+            Err(err) => {
+                eprintln!("record {record_number}: {err}");
+                failed += 1;
+                continue;
+            }
+        };
+
+        if in_flight.len() >= stream.max_in_flight {
+            tally(in_flight.join_next().await, &mut delivered, &mut failed);
+        }
+        let producer = producer.clone();
+        in_flight.spawn(async move {
+            send(&producer, &record)
+                .await
+                .map_err(|err| format!("record {record_number}: {err}"))
+        });
+    }
+    while let Some(result) = in_flight.join_next().await {
+        tally(Some(result), &mut delivered, &mut failed);
+    }
+
+    eprintln!("delivered: {delivered}, failed: {failed}");
+    if failed > 0 {
+        exit(1);
+    }
+}
+
+fn tally(
+    result: Option<Result<Result<(), String>, tokio::task::JoinError>>,
+    delivered: &mut usize,
+    failed: &mut usize,
+) {
+    match result {
+        Some(Ok(Ok(()))) => *delivered += 1,
+        Some(Ok(Err(err))) => {
+            eprintln!("Could not write message: {err}");
+            *failed += 1;
+        }
+        Some(Err(err)) => {
+            eprintln!("Could not write message: {err}");
+            *failed += 1;
         }
+        None => {}
+    }
+}
+
+/// Parse one json object with optional `key`, `payload`, `headers`, `partition`
+/// and `timestamp` fields. String payloads, keys and header values are decoded
+/// with the given encodings; any other json payload is sent as its serialized
+/// text. Headers are either an object of name to value or a list of
+/// `{"key", "value"}` objects.
+///
+/// `k12-json` also takes the destination from `topic` and ignores a zero or
+/// missing timestamp, which is how `read` prints messages without one.
+fn parse_json_record(
+    input: &[u8],
+    input_format: InputFormat,
+    payload_encoding: Encoding,
+    key_encoding: Encoding,
+    header_encoding: Encoding,
+) -> Result<Record, String> {
+    let value: Value = serde_json::from_slice(input).map_err(|e| format!("invalid json: {e}"))?;
+    let Value::Object(object) = value else {
+        return Err("expected a json object".to_string());
+    };
+    let mut record = Record::default();
+    for (field, value) in object {
+        match (field.as_str(), value) {
+            (_, Value::Null) => {}
+            ("payload", Value::String(payload)) => {
+                record.payload = Some(payload_encoding.decode(payload.as_bytes())?)
+            }
+            ("payload", payload) => record.payload = Some(payload.to_string().into_bytes()),
+            ("key", Value::String(key)) => record.key = Some(key_encoding.decode(key.as_bytes())?),
+            ("key", key) => record.key = Some(key.to_string().into_bytes()),
+            ("topic", Value::String(topic)) if input_format == InputFormat::K12Json => {
+                record.topic = Some(topic)
+            }
+            ("headers", headers) => record.headers = parse_json_headers(headers, header_encoding)?,
+            ("partition", Value::Number(partition)) => {
+                record.partition = partition
+                    .as_i64()
+                    .and_then(|partition| i32::try_from(partition).ok())
+                    .map(Some)
+                    .ok_or_else(|| format!("invalid partition: {partition}"))?
+            }
+            ("timestamp", Value::Number(timestamp)) => {
+                record.timestamp = timestamp
+                    .as_i64()
+                    .map(Some)
+                    .ok_or_else(|| format!("invalid timestamp: {timestamp}"))?
+            }
+            ("timestamp", Value::String(timestamp)) => {
+                record.timestamp = Some(parse_timestamp(&timestamp)?)
+            }
+            (field @ ("partition" | "timestamp"), value) => {
+                return Err(format!("invalid {field}: {value}"))
+            }
+            _ => {}
+        }
+    }
+    if input_format == InputFormat::K12Json && record.timestamp.is_some_and(|t| t <= 0) {
+        record.timestamp = None;
+    }
+    Ok(record)
+}
+
+fn parse_json_headers(headers: Value, encoding: Encoding) -> Result<RecordHeaders, String> {
+    let header_value = |value: Value| match value {
+        Value::Null => Ok(None),
+        Value::String(value) => encoding.decode(value.as_bytes()).map(Some),
+        value => Ok(Some(value.to_string().into_bytes())),
+    };
+    match headers {
+        Value::Object(headers) => headers
+            .into_iter()
+            .map(|(key, value)| Ok((key, header_value(value)?)))
+            .collect(),
+        Value::Array(headers) => headers
+            .into_iter()
+            .map(|header| match header {
+                Value::Object(mut header) => match header.remove("key") {
+                    Some(Value::String(key)) => Ok((
+                        key,
+                        header_value(header.remove("value").unwrap_or(Value::Null))?,
+                    )),
+                    _ => Err("header is missing a key".to_string()),
+                },
+                _ => Err("headers must be objects".to_string()),
+            })
+            .collect(),
+        _ => Err("headers must be an object or a list".to_string()),
+    }
+}
+
+/// Milliseconds since the epoch or an RFC 3339 datetime.
+fn parse_timestamp(timestamp: &str) -> Result<i64, String> {
+    timestamp.parse::<i64>().or_else(|_| {
+        DateTime::parse_from_rfc3339(timestamp)
+            .map(|datetime| datetime.timestamp_millis())
+            .map_err(|err| format!("{timestamp}: {err}"))
+    })
+}
+
+fn parse_delimiter(delimiter: &str) -> Option<u8> {
+    match delimiter {
+        "\\n" => Some(b'\n'),
+        "\\t" => Some(b'\t'),
+        "\\0" | "nul" => Some(0),
+        delimiter if delimiter.len() == 1 => delimiter.bytes().next(),
+        _ => None,
     }
 }
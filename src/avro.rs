@@ -0,0 +1,712 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Decodes Confluent wire format Avro: a zero magic byte, a 4 byte big endian
+/// schema id, then the Avro binary datum. Schemas come from a schema registry
+/// or from `<id>.avsc` files in a directory. Fetched schemas are kept per id.
+/// Failed registry lookups are kept too, so a registry that is down doesn't
+/// cost every message a request.
+pub struct Avro {
+    registry: Option<String>,
+    schema_dir: Option<PathBuf>,
+    timeout: Duration,
+    schemas: RefCell<HashMap<u32, Rc<Schema>>>,
+    failures: RefCell<HashMap<u32, Failure>>,
+}
+
+/// When a failed schema lookup is tried again.
+#[derive(Clone, Copy)]
+enum Retry {
+    /// With the next message, for lookups that are cheap.
+    Now,
+    /// After `RETRY_AFTER`, for registry errors that may go away.
+    Later,
+    /// Never, for ids the registry doesn't know and schemas that don't parse.
+    Never,
+}
+
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+struct Failure {
+    error: String,
+    /// `None` when the lookup is never tried again.
+    retry_at: Option<Instant>,
+}
+
+impl Avro {
+    pub fn new(registry: Option<String>, schema_dir: Option<String>, timeout: Duration) -> Self {
+        if registry.is_none() && schema_dir.is_none() {
+            eprintln!("--format-hint avro needs --schema-registry or --schema-dir.");
+            std::process::exit(1);
+        }
+        Avro {
+            registry: registry.map(|url| url.trim_end_matches('/').to_string()),
+            schema_dir: schema_dir.map(PathBuf::from),
+            timeout,
+            schemas: RefCell::new(HashMap::new()),
+            failures: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Decode a message payload or key to its json form.
+    pub fn decode(&self, bytes: &[u8]) -> Result<Value, String> {
+        let (magic, rest) = bytes.split_first().ok_or("empty")?;
+        let (id, datum) = rest
+            .split_first_chunk::<4>()
+            .filter(|_| *magic == 0)
+            .ok_or("not in schema registry wire format")?;
+        let id = u32::from_be_bytes(*id);
+        let schema = self.schema(id)?;
+        let mut reader = Reader {
+            bytes: datum,
+            depth: 0,
+        };
+        reader.value(&schema, &schema.root)
+    }
+
+    fn schema(&self, id: u32) -> Result<Rc<Schema>, String> {
+        if let Some(schema) = self.schemas.borrow().get(&id) {
+            return Ok(schema.clone());
+        }
+        if let Some(failure) = self.failures.borrow().get(&id) {
+            if failure.retry_at.is_none_or(|at| Instant::now() < at) {
+                return Err(failure.error.clone());
+            }
+        }
+        let schema = self
+            .fetch(id)
+            .and_then(|text| Schema::parse(&text).map_err(|err| (err, Retry::Never)));
+        match schema {
+            Ok(schema) => {
+                let schema = Rc::new(schema);
+                self.failures.borrow_mut().remove(&id);
+                self.schemas.borrow_mut().insert(id, schema.clone());
+                Ok(schema)
+            }
+            Err((err, retry)) => {
+                let error = format!("schema {id}: {err}");
+                let retry_at = match retry {
+                    Retry::Now => return Err(error),
+                    Retry::Later => Some(Instant::now() + RETRY_AFTER),
+                    Retry::Never => None,
+                };
+                let failure = Failure {
+                    error: error.clone(),
+                    retry_at,
+                };
+                self.failures.borrow_mut().insert(id, failure);
+                Err(error)
+            }
+        }
+    }
+
+    /// The schema text for `id`, from the directory first.
+    fn fetch(&self, id: u32) -> Result<String, (String, Retry)> {
+        if let Some(dir) = &self.schema_dir {
+            let path = dir.join(format!("{id}.avsc"));
+            match std::fs::read_to_string(&path) {
+                Ok(text) => return Ok(text),
+                Err(err) if self.registry.is_none() => {
+                    return Err((format!("{}: {}", path.display(), err), Retry::Now))
+                }
+                Err(_) => {}
+            }
+        }
+        let registry = self.registry.as_deref().expect("registry or schema dir");
+        // ureq ignores credentials in the url, so send them as basic auth.
+        let (url, credentials) = match registry.split_once("://") {
+            Some((scheme, rest)) => match rest.split_once('@') {
+                Some((credentials, host)) => (format!("{scheme}://{host}"), Some(credentials)),
+                None => (registry.to_string(), None),
+            },
+            None => (registry.to_string(), None),
+        };
+        let mut request = ureq::get(&format!("{url}/schemas/ids/{id}")).timeout(self.timeout);
+        if let Some(credentials) = credentials {
+            request = request.set(
+                "Authorization",
+                &format!("Basic {}", BASE64_STANDARD.encode(credentials)),
+            );
+        }
+        let later = |err: String| (err, Retry::Later);
+        let response = match request.call() {
+            Ok(response) => response,
+            Err(err @ ureq::Error::Status(404, _)) => return Err((err.to_string(), Retry::Never)),
+            Err(err) => return Err(later(err.to_string())),
+        };
+        let response = response
+            .into_string()
+            .map_err(|err| later(err.to_string()))?;
+        let response: Value =
+            serde_json::from_str(&response).map_err(|err| later(err.to_string()))?;
+        response
+            .get("schema")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .ok_or_else(|| later("registry response has no schema".to_string()))
+    }
+}
+
+/// A parsed schema. Named types are kept in `names` so records can refer to
+/// themselves.
+pub struct Schema {
+    root: Type,
+    names: HashMap<String, Type>,
+}
+
+#[derive(Clone)]
+enum Type {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Record(Vec<(String, Type)>),
+    Enum(Vec<String>),
+    Array(Box<Type>),
+    Map(Box<Type>),
+    Union(Vec<Type>),
+    Fixed(usize),
+    Named(String),
+}
+
+impl Schema {
+    fn parse(text: &str) -> Result<Self, String> {
+        let json: Value = serde_json::from_str(text).map_err(|err| err.to_string())?;
+        let mut names = HashMap::new();
+        let root = parse_type(&json, None, &mut names)?;
+        Ok(Schema { root, names })
+    }
+
+    /// Whether values of `kind` take no bytes at all, like `null` or a record
+    /// of nulls.
+    fn is_empty(&self, kind: &Type, visiting: &mut Vec<String>) -> bool {
+        match kind {
+            Type::Null => true,
+            Type::Fixed(size) => *size == 0,
+            Type::Record(fields) => fields.iter().all(|(_, kind)| self.is_empty(kind, visiting)),
+            Type::Named(name) => {
+                if visiting.contains(name) {
+                    return false;
+                }
+                visiting.push(name.clone());
+                let empty = self
+                    .names
+                    .get(name)
+                    .is_some_and(|kind| self.is_empty(kind, visiting));
+                visiting.pop();
+                empty
+            }
+            _ => false,
+        }
+    }
+}
+
+fn parse_type(
+    json: &Value,
+    namespace: Option<&str>,
+    names: &mut HashMap<String, Type>,
+) -> Result<Type, String> {
+    match json {
+        Value::String(name) => Ok(match name.as_str() {
+            "null" => Type::Null,
+            "boolean" => Type::Boolean,
+            "int" => Type::Int,
+            "long" => Type::Long,
+            "float" => Type::Float,
+            "double" => Type::Double,
+            "bytes" => Type::Bytes,
+            "string" => Type::String,
+            _ => Type::Named(full_name(name, namespace)),
+        }),
+        Value::Array(branches) => branches
+            .iter()
+            .map(|branch| parse_type(branch, namespace, names))
+            .collect::<Result<_, _>>()
+            .map(Type::Union),
+        Value::Object(object) => {
+            let kind = object.get("type").ok_or("type without a \"type\"")?;
+            let Some(kind) = kind.as_str() else {
+                // {"type": {...}} or {"type": [...]}, possibly with a logicalType.
+                return parse_type(kind, namespace, names);
+            };
+            let name = object.get("name").and_then(Value::as_str);
+            let namespace = object
+                .get("namespace")
+                .and_then(Value::as_str)
+                .or(namespace);
+            let full_name = name.map(|name| full_name(name, namespace));
+            // Names inside a named type default to its namespace.
+            let inner_namespace = full_name
+                .as_deref()
+                .and_then(|full_name| full_name.rsplit_once('.'))
+                .map(|(namespace, _)| namespace);
+            let parsed = match kind {
+                "record" | "error" => {
+                    let fields = object
+                        .get("fields")
+                        .and_then(Value::as_array)
+                        .ok_or("record without fields")?;
+                    let fields = fields
+                        .iter()
+                        .map(|field| {
+                            let name = field
+                                .get("name")
+                                .and_then(Value::as_str)
+                                .ok_or("field without a name")?;
+                            let kind = field.get("type").ok_or("field without a type")?;
+                            Ok((name.to_string(), parse_type(kind, inner_namespace, names)?))
+                        })
+                        .collect::<Result<_, String>>()?;
+                    Type::Record(fields)
+                }
+                "enum" => Type::Enum(
+                    object
+                        .get("symbols")
+                        .and_then(Value::as_array)
+                        .ok_or("enum without symbols")?
+                        .iter()
+                        .map(|symbol| symbol.as_str().unwrap_or_default().to_string())
+                        .collect(),
+                ),
+                "array" => Type::Array(Box::new(parse_type(
+                    object.get("items").ok_or("array without items")?,
+                    inner_namespace.or(namespace),
+                    names,
+                )?)),
+                "map" => Type::Map(Box::new(parse_type(
+                    object.get("values").ok_or("map without values")?,
+                    inner_namespace.or(namespace),
+                    names,
+                )?)),
+                "fixed" => Type::Fixed(
+                    object
+                        .get("size")
+                        .and_then(Value::as_u64)
+                        .ok_or("fixed without a size")? as usize,
+                ),
+                primitive => parse_type(&json!(primitive), namespace, names)?,
+            };
+            if let Some(full_name) = full_name {
+                if matches!(kind, "record" | "error" | "enum" | "fixed") {
+                    names.insert(full_name.clone(), parsed.clone());
+                    return Ok(Type::Named(full_name));
+                }
+            }
+            Ok(parsed)
+        }
+        _ => Err(format!("invalid type {json}")),
+    }
+}
+
+fn full_name(name: &str, namespace: Option<&str>) -> String {
+    match namespace {
+        Some(namespace) if !name.contains('.') && !namespace.is_empty() => {
+            format!("{namespace}.{name}")
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Arrays of values that take no bytes, such as nulls, can claim any length
+/// for free. Past this many a count is taken to be corrupt.
+const MAX_EMPTY_ITEMS: u64 = 1 << 20;
+
+/// How deep `Reader::value` may recurse, counting every record, union,
+/// array, map and named type on the way. Recursive schemas otherwise let a
+/// corrupt datum overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl Reader<'_> {
+    fn value(&mut self, schema: &Schema, kind: &Type) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("nested deeper than {MAX_DEPTH}"));
+        }
+        self.depth += 1;
+        let value = self.nested_value(schema, kind);
+        self.depth -= 1;
+        value
+    }
+
+    /// Values map to json as Avro's own json encoding does, except that union
+    /// values are not wrapped in an object naming their branch.
+    fn nested_value(&mut self, schema: &Schema, kind: &Type) -> Result<Value, String> {
+        Ok(match kind {
+            Type::Null => Value::Null,
+            Type::Boolean => json!(self.take(1)?[0] != 0),
+            Type::Int | Type::Long => json!(self.long()?),
+            Type::Float => json!(f32::from_le_bytes(self.array()?)),
+            Type::Double => json!(f64::from_le_bytes(self.array()?)),
+            Type::Bytes => {
+                let len = self.len()?;
+                json!(latin1(self.take(len)?))
+            }
+            Type::String => {
+                let len = self.len()?;
+                json!(String::from_utf8_lossy(self.take(len)?))
+            }
+            Type::Fixed(size) => json!(latin1(self.take(*size)?)),
+            Type::Enum(symbols) => {
+                let idx = self.long()?;
+                json!(usize::try_from(idx)
+                    .ok()
+                    .and_then(|idx| symbols.get(idx))
+                    .ok_or(format!("enum index {idx} out of range"))?)
+            }
+            Type::Record(fields) => {
+                let mut record = Map::new();
+                for (name, kind) in fields {
+                    record.insert(name.clone(), self.value(schema, kind)?);
+                }
+                Value::Object(record)
+            }
+            Type::Array(items) => {
+                let empty = schema.is_empty(items, &mut Vec::new());
+                let mut total = 0;
+                let mut array = Vec::new();
+                while let Some(count) = self.block(empty, &mut total)? {
+                    for _ in 0..count {
+                        array.push(self.value(schema, items)?);
+                    }
+                }
+                Value::Array(array)
+            }
+            Type::Map(values) => {
+                let mut total = 0;
+                let mut map = Map::new();
+                // Every entry has at least the length of its key.
+                while let Some(count) = self.block(false, &mut total)? {
+                    for _ in 0..count {
+                        let len = self.len()?;
+                        let key = String::from_utf8_lossy(self.take(len)?).into_owned();
+                        map.insert(key, self.value(schema, values)?);
+                    }
+                }
+                Value::Object(map)
+            }
+            Type::Union(branches) => {
+                let idx = self.long()?;
+                let branch = usize::try_from(idx)
+                    .ok()
+                    .and_then(|idx| branches.get(idx))
+                    .ok_or(format!("union index {idx} out of range"))?;
+                self.value(schema, branch)?
+            }
+            Type::Named(name) => {
+                let kind = schema
+                    .names
+                    .get(name)
+                    .ok_or(format!("unknown type {name}"))?;
+                self.value(schema, kind)?
+            }
+        })
+    }
+
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        if self.bytes.len() < len {
+            return Err("datum is truncated".to_string());
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let (head, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or("datum is truncated")?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    /// A zigzag varint.
+    fn long(&mut self) -> Result<i64, String> {
+        let mut value: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok((value >> 1) as i64 ^ -((value & 1) as i64));
+            }
+        }
+        Err("varint is too long".to_string())
+    }
+
+    fn len(&mut self) -> Result<usize, String> {
+        let len = self.long()?;
+        usize::try_from(len).map_err(|_| format!("negative length {len}"))
+    }
+
+    /// The item count of the next array or map block, `None` at the end. A
+    /// negative count is followed by the block's size in bytes. Counts are
+    /// untrusted: items take at least a byte each unless `empty`, and `total`
+    /// caps how many empty items all blocks add up to.
+    fn block(&mut self, empty: bool, total: &mut u64) -> Result<Option<u64>, String> {
+        let count = match self.long()? {
+            0 => return Ok(None),
+            count if count < 0 => {
+                self.long()?;
+                count.unsigned_abs()
+            }
+            count => count as u64,
+        };
+        *total = total.saturating_add(count);
+        if empty && *total > MAX_EMPTY_ITEMS {
+            return Err(format!("more than {MAX_EMPTY_ITEMS} empty items"));
+        }
+        if !empty && count > self.bytes.len() as u64 {
+            return Err("datum is truncated".to_string());
+        }
+        Ok(Some(count))
+    }
+}
+
+/// Bytes as Avro's json encoding writes them: one code point per byte.
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| char::from(byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `Avro` with its own schema directory holding `schema` as id 1.
+    fn avro(name: &str, schema: &str) -> (Avro, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("k12-avro-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("1.avsc"), schema).unwrap();
+        let avro = Avro::new(
+            None,
+            Some(dir.to_string_lossy().into_owned()),
+            Duration::from_secs(1),
+        );
+        (avro, dir)
+    }
+
+    /// `datum` in wire format with schema id `id`.
+    fn wire(id: u32, datum: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0];
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(datum);
+        bytes
+    }
+
+    /// A zigzag varint.
+    fn long(value: i64) -> Vec<u8> {
+        let mut value = ((value << 1) ^ (value >> 63)) as u64;
+        let mut bytes = Vec::new();
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+        bytes
+    }
+
+    #[test]
+    fn record() {
+        let (avro, _) = avro(
+            "record",
+            r#"{"type": "record", "name": "Order", "namespace": "shop", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string"},
+                {"name": "paid", "type": "boolean"},
+                {"name": "total", "type": "double"},
+                {"name": "at", "type": {"type": "long", "logicalType": "timestamp-millis"}}
+            ]}"#,
+        );
+        let mut datum = long(42);
+        datum.extend(long(3));
+        datum.extend(b"abc");
+        datum.push(1);
+        datum.extend(1.5f64.to_le_bytes());
+        datum.extend(long(1713508350144));
+        assert_eq!(
+            avro.decode(&wire(1, &datum)),
+            Ok(
+                json!({"id": 42, "name": "abc", "paid": true, "total": 1.5, "at": 1713508350144i64})
+            )
+        );
+    }
+
+    #[test]
+    fn union() {
+        let (avro, _) = avro(
+            "union",
+            r#"["null", "string", {"type": "enum", "name": "Status", "symbols": ["NEW", "DONE"]}]"#,
+        );
+        assert_eq!(avro.decode(&wire(1, &[0])), Ok(Value::Null));
+        assert_eq!(avro.decode(&wire(1, &[2, 4, b'h', b'i'])), Ok(json!("hi")));
+        assert_eq!(avro.decode(&wire(1, &[4, 2])), Ok(json!("DONE")));
+        assert_eq!(
+            avro.decode(&wire(1, &[6])),
+            Err("union index 3 out of range".to_string())
+        );
+    }
+
+    #[test]
+    fn array_blocks() {
+        let (avro, _) = avro("array", r#"{"type": "array", "items": "int"}"#);
+        // A block of two with its size in bytes, then a block of one.
+        let mut datum = long(-2);
+        datum.extend(long(2));
+        datum.extend(long(1));
+        datum.extend(long(2));
+        datum.extend(long(1));
+        datum.extend(long(-1));
+        datum.extend(long(0));
+        assert_eq!(avro.decode(&wire(1, &datum)), Ok(json!([1, 2, -1])));
+    }
+
+    #[test]
+    fn map_blocks() {
+        let (avro, _) = avro("map", r#"{"type": "map", "values": "long"}"#);
+        let mut datum = long(-2);
+        datum.extend(long(6));
+        datum.extend([2, b'a', 10, 2, b'b', 12]);
+        datum.extend(long(0));
+        assert_eq!(avro.decode(&wire(1, &datum)), Ok(json!({"a": 5, "b": 6})));
+    }
+
+    #[test]
+    fn named_types() {
+        let (avro, _) = avro(
+            "named",
+            r#"{"type": "record", "name": "shop.Line", "fields": [
+                {"name": "code", "type": {"type": "fixed", "name": "Code", "size": 2}},
+                {"name": "other", "type": "Code"},
+                {"name": "next", "type": ["null", "Line"]}
+            ]}"#,
+        );
+        let datum = [b'A', b'B', b'C', b'D', 2, b'E', b'F', b'G', b'H', 0];
+        assert_eq!(
+            avro.decode(&wire(1, &datum)),
+            Ok(json!({
+                "code": "AB",
+                "other": "CD",
+                "next": {"code": "EF", "other": "GH", "next": null}
+            }))
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let (avro, _) = avro(
+            "truncated",
+            r#"{"type": "record", "name": "R", "fields": [{"name": "s", "type": "string"}]}"#,
+        );
+        let truncated = Err("datum is truncated".to_string());
+        assert_eq!(avro.decode(&wire(1, &[10, b'a', b'b'])), truncated);
+        assert_eq!(avro.decode(&wire(1, &[])), truncated);
+        assert_eq!(
+            avro.decode(&[0, 0, 1]),
+            Err("not in schema registry wire format".to_string())
+        );
+        assert_eq!(
+            avro.decode(&[1, 0, 0, 0, 1, 0]),
+            Err("not in schema registry wire format".to_string())
+        );
+    }
+
+    #[test]
+    fn corrupt_block_counts() {
+        let (ints, _) = avro("corrupt-ints", r#"{"type": "array", "items": "int"}"#);
+        assert_eq!(
+            ints.decode(&wire(1, &long(100))),
+            Err("datum is truncated".to_string())
+        );
+        let (nulls, _) = avro("corrupt-nulls", r#"{"type": "array", "items": "null"}"#);
+        let mut datum = long(1 << 62);
+        datum.extend(long(0));
+        assert_eq!(
+            nulls.decode(&wire(1, &datum)),
+            Err(format!("more than {MAX_EMPTY_ITEMS} empty items"))
+        );
+        let mut datum = long(3);
+        datum.extend(long(0));
+        assert_eq!(
+            nulls.decode(&wire(1, &datum)),
+            Ok(json!([null, null, null]))
+        );
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let (avro, _) = avro(
+            "deep",
+            r#"{"type": "record", "name": "L", "fields": [{"name": "n", "type": ["null", "L"]}]}"#,
+        );
+        let mut datum = vec![2; 500_000];
+        datum.push(0);
+        assert_eq!(
+            avro.decode(&wire(1, &datum)),
+            Err(format!("nested deeper than {MAX_DEPTH}"))
+        );
+        assert_eq!(
+            avro.decode(&wire(1, &[2, 2, 0])),
+            Ok(json!({"n": {"n": {"n": null}}}))
+        );
+    }
+
+    /// A registry that answers every request with `status` and counts them.
+    fn registry(status: &str) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        let response =
+            format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                counted.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn registry_failures_are_kept() {
+        use std::sync::atomic::Ordering;
+        for status in ["404 Not Found", "503 Service Unavailable"] {
+            let (url, requests) = registry(status);
+            let avro = Avro::new(Some(url), None, Duration::from_secs(1));
+            let message = wire(3, &long(7));
+            let first = avro.decode(&message).unwrap_err();
+            assert!(first.starts_with("schema 3: "), "{first}");
+            assert_eq!(avro.decode(&message), Err(first));
+            assert_eq!(requests.load(Ordering::SeqCst), 1, "{status}");
+        }
+    }
+
+    #[test]
+    fn missing_schema_is_fetched_again() {
+        let (avro, dir) = avro("missing", r#""int""#);
+        let message = wire(2, &long(7));
+        assert!(avro.decode(&message).unwrap_err().starts_with("schema 2: "));
+        std::fs::write(dir.join("2.avsc"), r#""int""#).unwrap();
+        assert_eq!(avro.decode(&message), Ok(json!(7)));
+    }
+}
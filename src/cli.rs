@@ -1,4 +1,6 @@
-use clap::{App, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgGroup, SubCommand};
+
+use crate::common::{Encoding, Field};
 
 pub fn app() -> App<'static, 'static> {
     App::new("k2")
@@ -8,7 +10,24 @@ pub fn app() -> App<'static, 'static> {
         .arg(
             Arg::with_name("format-hint")
                 .long("format-hint")
-                .help("format hint will try to parse the message first. valid: json")
+                .help("format hint will try to parse the message first. valid: json, avro")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("schema-registry")
+                .long("schema-registry")
+                .value_name("URL")
+                .help("Schema registry for --format-hint avro. Credentials go in the url as user:password@")
+                .env("K12_SCHEMA_REGISTRY")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("schema-dir")
+                .long("schema-dir")
+                .value_name("DIR")
+                .help("Directory of <schema id>.avsc files for --format-hint avro, tried before the registry")
                 .takes_value(true)
                 .global(true),
         )
@@ -16,7 +35,48 @@ pub fn app() -> App<'static, 'static> {
             Arg::with_name("format")
                 .long("format")
                 .short("f")
-                .help("format of output. valid: json*, raw")
+                .help("format of output. valid: json*, raw, template. only read and tail default to json")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("template")
+                .long("template")
+                .help("line printed per message with --format template, e.g. '{{partition}}:{{offset}} {{payload | json_path(\".id\")}}'")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("payload-encoding")
+                .long("payload-encoding")
+                .help("encoding of payloads printed by read/tail and given to write. default utf8")
+                .possible_values(Encoding::VALUES)
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("key-encoding")
+                .long("key-encoding")
+                .help("encoding of message keys. default utf8")
+                .possible_values(Encoding::VALUES)
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("header-encoding")
+                .long("header-encoding")
+                .help("encoding of header values. default lossy")
+                .possible_values(Encoding::VALUES)
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("fields")
+                .long("fields")
+                .help("comma separated fields in json output. default topic,payload,timestamp")
+                .possible_values(Field::VALUES)
+                .require_delimiter(true)
+                .multiple(true)
                 .takes_value(true)
                 .global(true),
         )
@@ -68,14 +128,418 @@ pub fn app() -> App<'static, 'static> {
                 .takes_value(true)
                 .default_value("10000"),
         )
-        .subcommand(SubCommand::with_name("list").about("List items"))
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .help("Named context from the contexts file to use instead of the current one")
+                .env("K12_CONTEXT")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("property")
+                .short("X")
+                .help("librdkafka property key=value for every client. Overrides k12's own settings")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("config-file")
+                .long("config-file")
+                .help("File of librdkafka key=value properties for every client. -X wins over it")
+                .env("K12_CONFIG_FILE")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("security-protocol")
+                .long("security-protocol")
+                .help("Protocol used to talk to brokers")
+                .possible_values(&["plaintext", "ssl", "sasl_plaintext", "sasl_ssl"])
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("ssl-ca-location")
+                .long("ssl-ca-location")
+                .help("CA certificate file used to verify the brokers")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("ssl-certificate-location")
+                .long("ssl-certificate-location")
+                .help("Client certificate file for SSL client authentication")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("ssl-key-location")
+                .long("ssl-key-location")
+                .help("Client private key file for SSL client authentication")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("ssl-key-password")
+                .long("ssl-key-password")
+                .help("Password of the client private key")
+                .env("K12_SSL_KEY_PASSWORD")
+                .hide_env_values(true)
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("sasl-mechanism")
+                .long("sasl-mechanism")
+                .help("SASL mechanism. needs a sasl_* security protocol")
+                .possible_values(&["PLAIN", "SCRAM-SHA-256", "SCRAM-SHA-512", "OAUTHBEARER"])
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("sasl-username")
+                .long("sasl-username")
+                .help("SASL username for PLAIN and SCRAM")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("sasl-password")
+                .long("sasl-password")
+                .help("SASL password for PLAIN and SCRAM")
+                .env("K12_SASL_PASSWORD")
+                .hide_env_values(true)
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("sasl-oauth-token-command")
+                .long("sasl-oauth-token-command")
+                .help("Shell command printing an OAUTHBEARER token, or json with token and expires_in")
+                .takes_value(true)
+                .global(true),
+        )
         .subcommand(
-            SubCommand::with_name("write").about("Write to topic").arg(
-                Arg::with_name("topic")
-                    .long("topic")
-                    .help("Only fetch the metadata of the specified topic")
-                    .takes_value(true),
-            ),
+            SubCommand::with_name("list")
+                .about("List items")
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only list the specified topic. Can be repeated")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("topic-pattern")
+                        .long("topic-pattern")
+                        .value_name("REGEX")
+                        .help("Only list topics matching the regex")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("hide-internal")
+                        .long("hide-internal")
+                        .help("Hide internal topics such as __consumer_offsets"),
+                )
+                .arg(
+                    Arg::with_name("watermarks")
+                        .long("watermarks")
+                        .help("Show low/high offsets and message counts per partition"),
+                )
+                .arg(
+                    Arg::with_name("message-times")
+                        .long("message-times")
+                        .help("Also show the timestamp of the first and last message. implies --watermarks"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Describe and alter topic or broker configuration")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("describe")
+                        .about("Show every config value with its source and flags")
+                        .args(&config_resource_args()),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Set config values")
+                        .args(&config_resource_args())
+                        .arg(
+                            Arg::with_name("entry")
+                                .value_name("KEY=VALUE")
+                                .multiple(true)
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("reset")
+                        .about("Reset config values to their default")
+                        .args(&config_resource_args())
+                        .arg(
+                            Arg::with_name("key")
+                                .value_name("KEY")
+                                .multiple(true)
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("context")
+                .about("List, switch and show named cluster contexts")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("List contexts, marking the active one"))
+                .subcommand(
+                    SubCommand::with_name("switch")
+                        .about("Make a context the current one")
+                        .arg(Arg::with_name("name").value_name("CONTEXT").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Show the settings of a context, the active one by default")
+                        .arg(Arg::with_name("name").value_name("CONTEXT")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("groups")
+                .about("List consumer groups, or describe members and lag of the named groups")
+                .arg(
+                    Arg::with_name("name")
+                        .value_name("GROUP")
+                        .multiple(true)
+                        .help("Groups to describe"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reset-offsets")
+                .about("Rewrite a consumer group's committed offsets")
+                .arg(
+                    Arg::with_name("name")
+                        .value_name("GROUP")
+                        .required(true)
+                        .help("Group to reset"),
+                )
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .value_name("TOPIC[:PARTITIONS]")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .help("Topic to reset, optionally limited to partitions e.g. orders:0,2. Can be repeated")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to-earliest")
+                        .long("to-earliest")
+                        .help("Reset to the low watermark"),
+                )
+                .arg(
+                    Arg::with_name("to-latest")
+                        .long("to-latest")
+                        .help("Reset to the high watermark"),
+                )
+                .arg(
+                    Arg::with_name("to-offset")
+                        .long("to-offset")
+                        .value_name("NUMBER")
+                        .help("Reset to an absolute offset")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("shift-by")
+                        .long("shift-by")
+                        .value_name("NUMBER")
+                        .allow_hyphen_values(true)
+                        .help("Move the committed offset by NUMBER, negative to rewind")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("to-time")
+                        .long("to-time")
+                        .value_name("TIME")
+                        .help("Reset to the first message at or after TIME (RFC 3339 or e.g. '2 hours ago')")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("target")
+                        .args(&["to-earliest", "to-latest", "to-offset", "shift-by", "to-time"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Print the current and new offsets without committing"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("topic")
+                .about("Create and delete topics or add partitions")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Create a topic")
+                        .arg(Arg::with_name("name").value_name("TOPIC").required(true))
+                        .arg(
+                            Arg::with_name("partitions")
+                                .long("partitions")
+                                .help("Number of partitions. default 1")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("replication-factor")
+                                .long("replication-factor")
+                                .help("Replication factor. default 1")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("config")
+                                .long("config")
+                                .value_name("KEY=VALUE")
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("Topic config override e.g. cleanup.policy=compact. Can be repeated")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("Delete topics")
+                        .arg(
+                            Arg::with_name("name")
+                                .value_name("TOPIC")
+                                .multiple(true)
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("yes")
+                                .long("yes")
+                                .help("Do not ask to type the topic names to confirm"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add-partitions")
+                        .about("Increase the partition count of a topic")
+                        .arg(Arg::with_name("name").value_name("TOPIC").required(true))
+                        .arg(
+                            Arg::with_name("count")
+                                .long("count")
+                                .required(true)
+                                .help("New total number of partitions")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("truncate")
+                .about("Delete records before an offset or time")
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .value_name("TOPIC[:PARTITIONS]")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .help("Topic to truncate, optionally limited to partitions e.g. orders:0,2. Can be repeated")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("before-offset")
+                        .long("before-offset")
+                        .value_name("NUMBER")
+                        .help("Delete records before this offset")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("before-time")
+                        .long("before-time")
+                        .value_name("TIME")
+                        .help("Delete records before TIME (RFC 3339 or e.g. '2 hours ago')")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("before")
+                        .args(&["before-offset", "before-time"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Print the current and new low watermarks without deleting"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("write")
+                .about("Write to topic")
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .help("Only fetch the metadata of the specified topic")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("lines")
+                        .long("lines")
+                        .help("Send each line of stdin as its own message"),
+                )
+                .arg(
+                    Arg::with_name("delimiter")
+                        .long("delimiter")
+                        .value_name("BYTE")
+                        .help("Send each record split on BYTE as its own message. e.g. '\\0', '\\t', ';'")
+                        .conflicts_with("lines")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("input-format")
+                        .long("input-format")
+                        .help("raw sends each record as the payload. json reads one object per line with key, payload, headers, partition and timestamp fields. k12-json reads read/tail json output, topic included")
+                        .possible_values(&["raw", "json", "k12-json"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .help("Message key, decoded with --key-encoding")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("header")
+                        .long("header")
+                        .value_name("NAME=VALUE")
+                        .help("Message header, its value decoded with --header-encoding. Can be repeated")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("partition")
+                        .long("partition")
+                        .help("Partition to write to. default is the partitioner")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("keep-partition")
+                        .long("keep-partition")
+                        .help("Keep the partition of k12-json records that --topic sends to another topic"),
+                )
+                .arg(
+                    Arg::with_name("timestamp")
+                        .long("timestamp")
+                        .help("Message timestamp in epoch milliseconds or RFC 3339")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-in-flight")
+                        .long("max-in-flight")
+                        .value_name("COUNT")
+                        .help("Deliveries outstanding at once when streaming records. default 1000")
+                        .takes_value(true),
+                ),
         )
         .subcommand(
             SubCommand::with_name("read")
@@ -84,7 +548,7 @@ pub fn app() -> App<'static, 'static> {
                     Arg::with_name("topic")
                         .long("topic")
                         .multiple(true)
-                        .help("Only fetch the metadata of the specified topic")
+                        .help("Topic to read. Repeat to read several topics together")
                         .takes_value(true),
                 )
                 .arg(
@@ -121,15 +585,125 @@ pub fn app() -> App<'static, 'static> {
                         .value_name("OFFSET")
                         .help("End offset (e.g., '30 minutes ago', '4 months from now')")
                         .takes_value(true),
-                ),
+                )
+                .arg(
+                    Arg::with_name("sorted")
+                        .long("sorted")
+                        .help("Merge partitions by message timestamp. Ties go to topic, partition then offset"),
+                )
+                .arg(
+                    Arg::with_name("sorted-buffer")
+                        .long("sorted-buffer")
+                        .value_name("COUNT")
+                        .help("Messages buffered per partition with --sorted before it is paused. default 1000")
+                        .requires("sorted")
+                        .takes_value(true),
+                )
+                .arg(filter_arg())
+                .args(&jq_args()),
         )
         .subcommand(
-            SubCommand::with_name("tail").about("Tail items").arg(
-                Arg::with_name("topic")
-                    .long("topic")
-                    .multiple(true)
-                    .help("tail the specified topic")
-                    .takes_value(true),
-            ),
+            SubCommand::with_name("tail")
+                .about("Tail items")
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .multiple(true)
+                        .help("tail the specified topic, or TOPIC:PARTITION[,PARTITION]")
+                        .required_unless("topic-pattern")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("topic-pattern")
+                        .long("topic-pattern")
+                        .value_name("REGEX")
+                        .help("Subscribe to every topic matching REGEX, including ones created later")
+                        .conflicts_with_all(&["topic", "offset-tail", "since"])
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("from-beginning")
+                        .long("from-beginning")
+                        .help("Start from the oldest message of every partition"),
+                )
+                .arg(
+                    Arg::with_name("from-end")
+                        .long("from-end")
+                        .help("Only show messages produced from now on"),
+                )
+                .arg(
+                    Arg::with_name("offset-tail")
+                        .long("offset-tail")
+                        .value_name("N")
+                        .help("Start N messages before the end of every partition")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .value_name("TIME")
+                        .help("Start at the first message at or after TIME, e.g. \"5 minutes ago\"")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("commit-group")
+                        .long("commit-group")
+                        .value_name("GROUP")
+                        .help("Join GROUP and commit what was shown. By default tail uses a throwaway group id and commits nothing")
+                        .takes_value(true),
+                )
+                .arg(filter_arg())
+                .args(&jq_args())
+                .group(ArgGroup::with_name("start").args(&[
+                    "from-beginning",
+                    "from-end",
+                    "offset-tail",
+                    "since",
+                ])),
         )
 }
+
+/// `--topic` or `--broker`, shared by the config subcommands.
+fn config_resource_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("topic")
+            .long("topic")
+            .help("Topic to configure")
+            .required_unless("broker")
+            .conflicts_with("broker")
+            .takes_value(true),
+        Arg::with_name("broker")
+            .long("broker")
+            .value_name("ID")
+            .help("Broker id to configure")
+            .takes_value(true),
+    ]
+}
+
+/// `--filter`, shared by read and tail.
+fn filter_arg() -> Arg<'static, 'static> {
+    Arg::with_name("filter")
+        .long("filter")
+        .value_name("EXPR")
+        .help("Only show messages matching EXPR: payload~REGEX, key~REGEX (!~ negates), .json.path OP VALUE with OP one of == != > >= < <=, or header:NAME==VALUE. Repeat to require all")
+        .multiple(true)
+        .number_of_values(1)
+        .takes_value(true)
+}
+
+/// `--select` and `--transform`, shared by read and tail.
+fn jq_args() -> [Arg<'static, 'static>; 2] {
+    [
+        Arg::with_name("select")
+            .long("select")
+            .value_name("JQ")
+            .help("Run a jq expression on json payloads and print only its results")
+            .conflicts_with("transform")
+            .takes_value(true),
+        Arg::with_name("transform")
+            .long("transform")
+            .value_name("JQ")
+            .help("Replace json payloads with the results of a jq expression")
+            .takes_value(true),
+    ]
+}
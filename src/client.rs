@@ -0,0 +1,197 @@
+use base64::Engine;
+use rdkafka::client::{ClientContext, OAuthToken};
+use rdkafka::config::{ClientConfig, FromClientConfigAndContext, RDKafkaLogLevel};
+use rdkafka::consumer::{ConsumerContext, Rebalance};
+use rdkafka::error::KafkaResult;
+use rdkafka::topic_partition_list::TopicPartitionList;
+use std::error::Error;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Client context shared by every producer, consumer and admin client. With
+/// `OAUTH` it fetches SASL OAUTHBEARER tokens from `--sasl-oauth-token-command`.
+/// Without it librdkafka's own token handling is left alone, which
+/// `sasl.oauthbearer.method=oidc` and unsecured JWTs rely on. For consumers
+/// it also reports partition assignment changes on stderr.
+#[derive(Clone, Default)]
+pub struct K12Context<const OAUTH: bool = false> {
+    token_command: Option<String>,
+}
+
+impl<const OAUTH: bool> ClientContext for K12Context<OAUTH> {
+    const ENABLE_REFRESH_OAUTH_TOKEN: bool = OAUTH;
+
+    fn generate_oauth_token(
+        &self,
+        _oauthbearer_config: Option<&str>,
+    ) -> Result<OAuthToken, Box<dyn Error>> {
+        let command = self
+            .token_command
+            .as_deref()
+            .ok_or("OAUTHBEARER needs --sasl-oauth-token-command")?;
+        let output = Command::new("sh").arg("-c").arg(command).output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "token command failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+        parse_token(String::from_utf8(output.stdout)?.trim())
+    }
+}
+
+/// Only subscribed consumers rebalance, so this is where `tail` reports what
+/// it was given or lost.
+impl<const OAUTH: bool> ConsumerContext for K12Context<OAUTH> {
+    fn post_rebalance(&self, rebalance: &Rebalance<'_>) {
+        match rebalance {
+            Rebalance::Assign(tpl) => eprintln!("Assigned: {}", partition_names(tpl)),
+            Rebalance::Revoke(tpl) => eprintln!("Revoked: {}", partition_names(tpl)),
+            Rebalance::Error(err) => eprintln!("Rebalance failed: {}", err),
+        }
+    }
+}
+
+fn partition_names(tpl: &TopicPartitionList) -> String {
+    if tpl.count() == 0 {
+        return "nothing".to_string();
+    }
+    tpl.elements()
+        .iter()
+        .map(|elem| format!("{}/{}", elem.topic(), elem.partition()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A `ClientConfig` together with what every client's context is created
+/// with. `overrides` are the user's own properties, applied over k12's
+/// settings when the client is created.
+#[derive(Clone)]
+pub struct KafkaConfig {
+    config: ClientConfig,
+    token_command: Option<String>,
+    overrides: Vec<(String, String)>,
+}
+
+impl KafkaConfig {
+    pub fn new(token_command: Option<String>, overrides: Vec<(String, String)>) -> Self {
+        KafkaConfig {
+            config: ClientConfig::new(),
+            token_command,
+            overrides,
+        }
+    }
+
+    /// Whether clients need a `K12Context<true>` to run the token command.
+    pub fn oauth(&self) -> bool {
+        self.token_command.is_some()
+    }
+
+    pub fn set<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) -> &mut Self {
+        self.config.set(key, value);
+        self
+    }
+
+    pub fn set_log_level(&mut self, log_level: RDKafkaLogLevel) -> &mut Self {
+        self.config.set_log_level(log_level);
+        self
+    }
+
+    pub fn create<T: FromClientConfigAndContext<K12Context<OAUTH>>, const OAUTH: bool>(
+        &self,
+    ) -> KafkaResult<T> {
+        let mut config = self.config.clone();
+        for (key, value) in &self.overrides {
+            config.set(key, value);
+        }
+        let context = K12Context {
+            token_command: self.token_command.clone(),
+        };
+        config.create_with_context(context)
+    }
+}
+
+/// Parse a `key=value` librdkafka property.
+pub fn parse_property(property: &str) -> (String, String) {
+    match property.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            (key.trim().to_string(), value.trim().to_string())
+        }
+        _ => {
+            eprintln!("Invalid property {property}, expected key=value");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Read librdkafka properties from a file of `key=value` lines. Blank lines
+/// and lines starting with `#` are skipped.
+pub fn read_properties(path: &str) -> Vec<(String, String)> {
+    let content = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Could not read config file {path}: {err}");
+        std::process::exit(1);
+    });
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_property)
+        .collect()
+}
+
+/// A token command prints either the bare token or a JSON object with
+/// `token` (or `access_token`), and optionally `expires_in` seconds and
+/// `principal`. Anything missing is taken from the JWT claims when the token
+/// is one, with a lifetime of an hour as the last resort.
+fn parse_token(output: &str) -> Result<OAuthToken, Box<dyn Error>> {
+    let now_ms = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    let (token, expires_in, principal) = match serde_json::from_str::<serde_json::Value>(output) {
+        Ok(serde_json::Value::Object(object)) => (
+            object
+                .get("token")
+                .or_else(|| object.get("access_token"))
+                .and_then(|token| token.as_str())
+                .ok_or("token command output has no token or access_token")?
+                .to_string(),
+            object.get("expires_in").and_then(|secs| secs.as_i64()),
+            object
+                .get("principal")
+                .and_then(|principal| principal.as_str())
+                .map(ToString::to_string),
+        ),
+        _ => (output.to_string(), None, None),
+    };
+    if token.is_empty() {
+        return Err("token command printed no token".into());
+    }
+    let claims = jwt_claims(&token);
+    let claim = |name: &str| claims.as_ref().and_then(|claims| claims.get(name).cloned());
+    let lifetime_ms = match expires_in {
+        Some(secs) => now_ms + secs * 1000,
+        None => claim("exp")
+            .and_then(|exp| exp.as_i64())
+            .map(|exp| exp * 1000)
+            .unwrap_or(now_ms + 3_600_000),
+    };
+    let principal_name = principal
+        .or_else(|| claim("sub").and_then(|sub| sub.as_str().map(ToString::to_string)))
+        .unwrap_or_else(|| "k12".to_string());
+    Ok(OAuthToken {
+        token,
+        principal_name,
+        lifetime_ms,
+    })
+}
+
+fn jwt_claims(token: &str) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    match serde_json::from_slice(&bytes).ok()? {
+        serde_json::Value::Object(claims) => Some(claims),
+        _ => None,
+    }
+}
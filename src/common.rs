@@ -1,23 +1,155 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
 use rdkafka::{
     config::RDKafkaLogLevel,
-    message::{BorrowedHeaders, BorrowedMessage, Headers, Message},
+    message::{Header, Headers, Message, Timestamp},
 };
 use serde_json::json;
+use std::borrow::Cow;
+
+use crate::avro::Avro;
+use crate::jq::{Jq, JqMode};
+use crate::template::{Placeholder, Template};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd)]
 pub enum FormatHint {
     Json,
+    /// Confluent wire format avro, decoded to json.
+    Avro,
     None,
 }
 impl From<&str> for FormatHint {
     fn from(hint: &str) -> Self {
         match hint {
             "json" => FormatHint::Json,
+            "avro" => FormatHint::Avro,
             _ => FormatHint::None,
         }
     }
 }
 
+/// How message bytes are turned into text on output, and text back into bytes
+/// on input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Strict utf-8. Invalid payloads are reported and printed as empty.
+    Utf8,
+    /// utf-8 with invalid sequences replaced by U+FFFD.
+    Lossy,
+    Hex,
+    Base64,
+}
+impl From<&str> for Encoding {
+    fn from(value: &str) -> Self {
+        match value {
+            "hex" => Self::Hex,
+            "base64" => Self::Base64,
+            "lossy" => Self::Lossy,
+            _ => Self::Utf8,
+        }
+    }
+}
+impl Encoding {
+    pub const VALUES: &'static [&'static str] = &["utf8", "hex", "base64", "lossy"];
+
+    pub fn encode<'a>(&self, bytes: &'a [u8]) -> Cow<'a, str> {
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(s) => Cow::Borrowed(s),
+                Err(e) => {
+                    eprintln!("Error while deserializing message payload: {:?}", e);
+                    Cow::Borrowed("")
+                }
+            },
+            Encoding::Lossy => String::from_utf8_lossy(bytes),
+            Encoding::Hex => Cow::Owned(hex::encode(bytes)),
+            Encoding::Base64 => Cow::Owned(BASE64_STANDARD.encode(bytes)),
+        }
+    }
+
+    pub fn decode(&self, text: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            Encoding::Utf8 | Encoding::Lossy => Ok(text.to_vec()),
+            Encoding::Hex => hex::decode(text.trim_ascii()).map_err(|e| format!("{e}")),
+            Encoding::Base64 => BASE64_STANDARD
+                .decode(text.trim_ascii())
+                .map_err(|e| format!("{e}")),
+        }
+    }
+
+    /// Whether the encoded form is the message text itself, so format hints apply.
+    pub fn is_text(&self) -> bool {
+        matches!(self, Encoding::Utf8 | Encoding::Lossy)
+    }
+}
+
+/// Fields emitted by the json format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Topic,
+    Payload,
+    Timestamp,
+    TimestampType,
+    Key,
+    Partition,
+    Offset,
+    Headers,
+}
+impl From<&str> for Field {
+    fn from(value: &str) -> Self {
+        match value {
+            "topic" => Self::Topic,
+            "timestamp" => Self::Timestamp,
+            "timestamp_type" => Self::TimestampType,
+            "key" => Self::Key,
+            "partition" => Self::Partition,
+            "offset" => Self::Offset,
+            "headers" => Self::Headers,
+            _ => Self::Payload,
+        }
+    }
+}
+impl Field {
+    pub const VALUES: &'static [&'static str] = &[
+        "topic",
+        "payload",
+        "timestamp",
+        "timestamp_type",
+        "key",
+        "partition",
+        "offset",
+        "headers",
+        "all",
+    ];
+    pub const DEFAULT: &'static [Field] = &[Field::Topic, Field::Payload, Field::Timestamp];
+    pub const ALL: &'static [Field] = &[
+        Field::Topic,
+        Field::Payload,
+        Field::Timestamp,
+        Field::TimestampType,
+        Field::Key,
+        Field::Partition,
+        Field::Offset,
+        Field::Headers,
+    ];
+
+    /// Parse the values given to `--fields`, expanding `all`.
+    pub fn parse_list<'a>(values: impl Iterator<Item = &'a str>) -> Vec<Field> {
+        let mut fields = Vec::new();
+        for value in values {
+            let expanded = match value {
+                "all" => Field::ALL.to_vec(),
+                value => vec![value.into()],
+            };
+            for field in expanded {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+        }
+        fields
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd)]
 pub enum Verbosity {
     Silent,
@@ -53,43 +185,140 @@ pub struct FormatConfig {
     pub verbosity: Verbosity,
     pub format_hint: Option<FormatHint>,
     pub format: Format,
+    /// Prefix raw output with the topic even at low verbosity, set when
+    /// messages from several topics are interleaved.
+    pub show_topic: bool,
+    pub payload_encoding: Encoding,
+    pub key_encoding: Encoding,
+    pub header_encoding: Encoding,
+    pub fields: Vec<Field>,
+    /// `--select` or `--transform` applied to json payloads before printing.
+    pub jq: Option<Jq>,
+    /// Set by `--format template`, replacing the raw and json layouts.
+    pub template: Option<Template>,
+    /// Set by `--format-hint avro`.
+    pub avro: Option<Avro>,
+}
+
+impl FormatConfig {
+    /// Reports such as `list` read best as text, so they only print json when
+    /// `--format` is given explicitly.
+    pub fn text_by_default(self, format: Option<&str>) -> Self {
+        FormatConfig {
+            format: format.map(Format::from).unwrap_or(Format::Raw),
+            ..self
+        }
+    }
 }
 
-pub fn print_message(m: &BorrowedMessage, format_config: &FormatConfig) {
-    let payload = match m.payload_view::<str>() {
-        None => "",
-        Some(Ok(s)) => s,
-        Some(Err(e)) => {
-            eprintln!("Error while deserializing message payload: {:?}", e);
-            ""
+pub fn print_message<M: Message>(m: &M, format_config: &FormatConfig) {
+    let location = || format!("{}/{}@{}", m.topic(), m.partition(), m.offset());
+    let payload_json = decode_avro(format_config, m.payload(), "payload", location);
+    // Keys are often plain strings even when payloads are avro.
+    let key = m.key().filter(|key| key.first() == Some(&0));
+    let key_json = decode_avro(format_config, key, "key", location);
+    let Some(jq) = &format_config.jq else {
+        return display(m, format_config, payload_json, key_json).print();
+    };
+    let Some(input) = payload_json.or_else(|| {
+        m.payload()
+            .and_then(|payload| serde_json::from_slice(payload).ok())
+    }) else {
+        if format_config.verbosity != Verbosity::Silent {
+            eprintln!("Skipping {}: payload is not json", location());
+        }
+        return;
+    };
+    let results = jq.run(input).unwrap_or_else(|err| {
+        eprintln!("Skipping {}: {err}", location());
+        Vec::new()
+    });
+    for result in results {
+        match jq.mode {
+            JqMode::Select => println!("{result}"),
+            JqMode::Transform => display(m, format_config, Some(result), key_json.clone()).print(),
         }
+    }
+}
+
+/// Decode with `--format-hint avro`. Failures are reported and leave the
+/// bytes to be printed as they are.
+fn decode_avro(
+    format_config: &FormatConfig,
+    bytes: Option<&[u8]>,
+    what: &str,
+    location: impl Fn() -> String,
+) -> Option<serde_json::Value> {
+    let avro = format_config.avro.as_ref()?;
+    avro.decode(bytes?)
+        .map_err(|err| {
+            if format_config.verbosity != Verbosity::Silent {
+                eprintln!("Could not decode {what} of {}: {err}", location());
+            }
+        })
+        .ok()
+}
+
+/// `payload_json` and `key_json` replace the payload and key, as decoded avro
+/// or the result of `--transform`.
+fn display<'a, M: Message>(
+    m: &'a M,
+    format_config: &'a FormatConfig,
+    payload_json: Option<serde_json::Value>,
+    key_json: Option<serde_json::Value>,
+) -> DataDisplay<'a> {
+    let payload = match &payload_json {
+        Some(payload) => Cow::Owned(payload.to_string()),
+        None => m
+            .payload()
+            .map(|payload| format_config.payload_encoding.encode(payload))
+            .unwrap_or_default(),
+    };
+    let key = match key_json {
+        Some(serde_json::Value::String(key)) => Some(Cow::Owned(key)),
+        Some(key) => Some(Cow::Owned(key.to_string())),
+        None => m.key().map(|key| format_config.key_encoding.encode(key)),
     };
 
-    let display = DataDisplay {
-        key: m.key(),
+    DataDisplay {
+        key,
         topic: m.topic(),
         partition: m.partition(),
         offset: m.offset(),
         timestamp: m.timestamp().to_millis().unwrap_or_default(),
-        headers: m.headers(),
+        timestamp_type: match m.timestamp() {
+            Timestamp::NotAvailable => "not_available",
+            Timestamp::CreateTime(_) => "create_time",
+            Timestamp::LogAppendTime(_) => "log_append_time",
+        },
+        headers: m.headers().map(|headers| {
+            (0..headers.count())
+                .filter_map(|idx| headers.try_get(idx))
+                .collect()
+        }),
         payload,
+        payload_json,
         format_config,
-    };
-    display.print()
+    }
 }
 
 struct DataDisplay<'a> {
-    key: Option<&'a [u8]>,
+    key: Option<Cow<'a, str>>,
     topic: &'a str,
     partition: i32,
     offset: i64,
     timestamp: i64,
-    payload: &'a str,
-    headers: Option<&'a BorrowedHeaders>,
+    timestamp_type: &'static str,
+    payload: Cow<'a, str>,
+    payload_json: Option<serde_json::Value>,
+    headers: Option<Vec<Header<'a, &'a [u8]>>>,
     format_config: &'a FormatConfig,
 }
 impl<'a> DataDisplay<'a> {
     pub fn print(&self) {
+        if let Some(template) = &self.format_config.template {
+            return self.as_template(template);
+        }
         match self.format_config.format {
             Format::Json => self.as_json(),
             Format::Raw => self.as_raw(),
@@ -99,45 +328,108 @@ impl<'a> DataDisplay<'a> {
         match self.format_config.verbosity {
             Verbosity::TooMuch => {
                 println!(
-                    "key:'{:?}', topic:'{}', partition:{}, offset:{}, timestamp:{}, payload:{}",
-                    self.key, self.topic, self.partition, self.offset, self.timestamp, self.payload
+                    "key:'{}', topic:'{}', partition:{}, offset:{}, timestamp:{}, payload:{}",
+                    self.key.as_deref().unwrap_or_default(),
+                    self.topic,
+                    self.partition,
+                    self.offset,
+                    self.timestamp,
+                    self.payload
                 );
 
-                if let Some(headers) = self.headers {
+                if let Some(headers) = &self.headers {
                     for header in headers.iter() {
                         println!("  Header {:#?}: {:?}", header.key, header.value);
                     }
                 }
             }
+            Verbosity::Silent if self.format_config.show_topic => {
+                println!("{} - {}", self.topic, self.payload)
+            }
             Verbosity::Silent => println!("{}", self.payload),
-            Verbosity::Soft => println!("{} - {}", self.offset, self.payload),
-            Verbosity::Loud => println!("{} - {} - {}", self.topic, self.offset, self.payload),
+            Verbosity::Soft if !self.format_config.show_topic => {
+                println!("{} - {}", self.offset, self.payload)
+            }
+            Verbosity::Soft | Verbosity::Loud => {
+                println!("{} - {} - {}", self.topic, self.offset, self.payload)
+            }
         }
     }
     pub fn as_json(&self) {
-        let payload = match self
-            .format_config
-            .format_hint
-            .as_ref()
-            .unwrap_or(&FormatHint::None)
-        {
-            FormatHint::Json => serde_json::from_str(self.payload).unwrap_or_else(|_e| {
-                match self.format_config.verbosity {
-                    Verbosity::Silent => {}
-                    _ => {
-                        eprintln!("Error parsing json string: {}", self.payload);
-                    }
-                };
-                serde_json::Value::Null
-            }),
-            _ => serde_json::Value::String(self.payload.into()),
+        let format_hint = match self.format_config.payload_encoding.is_text() {
+            true => self.format_config.format_hint.as_ref(),
+            false => None,
         };
-        let json = json!({
-            "topic": self.topic,
-            "payload": payload,
-            "timestamp": self.timestamp,
+        let payload = match &self.payload_json {
+            Some(payload) => payload.clone(),
+            None => match format_hint.unwrap_or(&FormatHint::None) {
+                FormatHint::Json => serde_json::from_str(&self.payload).unwrap_or_else(|_e| {
+                    match self.format_config.verbosity {
+                        Verbosity::Silent => {}
+                        _ => {
+                            eprintln!("Error parsing json string: {}", self.payload);
+                        }
+                    };
+                    serde_json::Value::Null
+                }),
+                _ => serde_json::Value::String(self.payload.to_string()),
+            },
+        };
+        let mut json = serde_json::Map::new();
+        for field in &self.format_config.fields {
+            let (name, value) = match field {
+                Field::Topic => ("topic", json!(self.topic)),
+                Field::Payload => ("payload", payload.clone()),
+                Field::Timestamp => ("timestamp", json!(self.timestamp)),
+                Field::TimestampType => ("timestamp_type", json!(self.timestamp_type)),
+                Field::Key => ("key", json!(self.key)),
+                Field::Partition => ("partition", json!(self.partition)),
+                Field::Offset => ("offset", json!(self.offset)),
+                Field::Headers => ("headers", self.headers_json()),
+            };
+            json.insert(name.to_string(), value);
+        }
+        println!("{}", serde_json::Value::Object(json));
+    }
+    pub fn as_template(&self, template: &Template) {
+        let line = template.render(|placeholder| match placeholder {
+            Placeholder::Topic => Some(self.topic.to_string()),
+            Placeholder::Partition => Some(self.partition.to_string()),
+            Placeholder::Offset => Some(self.offset.to_string()),
+            Placeholder::Key => self.key.as_deref().map(ToString::to_string),
+            Placeholder::Payload => Some(self.payload.to_string()),
+            Placeholder::Timestamp => Some(self.timestamp.to_string()),
+            Placeholder::TimestampType => Some(self.timestamp_type.to_string()),
+            Placeholder::Headers => Some(self.headers_json().to_string()),
+            Placeholder::Header(name) => self
+                .headers
+                .iter()
+                .flatten()
+                .find(|header| header.key == name)
+                .and_then(|header| header.value)
+                .map(|value| {
+                    self.format_config
+                        .header_encoding
+                        .encode(value)
+                        .into_owned()
+                }),
         });
-        println!("{json}");
+        println!("{line}");
+    }
+    /// Headers as a list of `{"key", "value"}` objects, keeping duplicates and
+    /// order. Values are in `--header-encoding`, or null when the header has no
+    /// value.
+    fn headers_json(&self) -> serde_json::Value {
+        self.headers
+            .iter()
+            .flatten()
+            .map(|header| {
+                json!({
+                    "key": header.key,
+                    "value": header.value.map(|value| self.format_config.header_encoding.encode(value)),
+                })
+            })
+            .collect()
     }
 }
 
@@ -153,3 +445,50 @@ pub fn kafka_debug_from_int(level: u8) -> RDKafkaLogLevel {
         _ => RDKafkaLogLevel::Debug,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BYTES: &[u8] = b"\x00k12\xff\n";
+
+    #[test]
+    fn round_trips() {
+        for encoding in [Encoding::Hex, Encoding::Base64] {
+            let text = encoding.encode(BYTES);
+            assert_eq!(encoding.decode(text.as_bytes()).unwrap(), BYTES);
+        }
+        let text = Encoding::Utf8.encode("grüße\n".as_bytes());
+        assert_eq!(text, "grüße\n");
+        assert_eq!(
+            Encoding::Utf8.decode(text.as_bytes()).unwrap(),
+            text.as_bytes()
+        );
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(Encoding::Hex.encode(BYTES), "006b3132ff0a");
+        assert_eq!(Encoding::Base64.encode(BYTES), "AGsxMv8K");
+        assert_eq!(Encoding::Utf8.encode(BYTES), "");
+        assert_eq!(Encoding::Lossy.encode(BYTES), "\0k12\u{fffd}\n");
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(Encoding::Hex.decode(b" 006b3132ff0a\n").unwrap(), BYTES);
+        assert_eq!(Encoding::Base64.decode(b"AGsxMv8K\n").unwrap(), BYTES);
+        assert_eq!(Encoding::Utf8.decode(b" a\n").unwrap(), b" a\n");
+        assert!(Encoding::Hex.decode(b"0g").is_err());
+        assert!(Encoding::Hex.decode(b"abc").is_err());
+        assert!(Encoding::Base64.decode(b"AGs*").is_err());
+    }
+
+    #[test]
+    fn names() {
+        for name in Encoding::VALUES {
+            let encoding = Encoding::from(*name);
+            assert_eq!(encoding.is_text(), matches!(*name, "utf8" | "lossy"));
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+/// The contexts file, `~/.config/k12/config.toml` unless `K12_CONTEXT_FILE`
+/// points somewhere else.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ContextFile {
+    pub current_context: Option<String>,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, Context>,
+}
+
+/// A named cluster. Every setting is named after the global option it stands
+/// in for, and options given on the command line win over it.
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Context {
+    pub brokers: Option<String>,
+    pub group: Option<String>,
+    pub client_id: Option<String>,
+    pub format: Option<String>,
+    pub timeout: Option<u64>,
+    pub security_protocol: Option<String>,
+    pub ssl_ca_location: Option<String>,
+    pub ssl_certificate_location: Option<String>,
+    pub ssl_key_location: Option<String>,
+    pub ssl_key_password: Option<String>,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub sasl_oauth_token_command: Option<String>,
+    pub config_file: Option<String>,
+    pub schema_registry: Option<String>,
+    /// librdkafka properties, applied before `--config-file` and `-X`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
+}
+
+impl Context {
+    /// The value this context has for the global option `arg`.
+    pub fn get(&self, arg: &str) -> Option<String> {
+        let value = match arg {
+            "brokers" => &self.brokers,
+            "group" => &self.group,
+            "client-id" => &self.client_id,
+            "format" => &self.format,
+            "timeout" => return self.timeout.map(|timeout| timeout.to_string()),
+            "security-protocol" => &self.security_protocol,
+            "ssl-ca-location" => &self.ssl_ca_location,
+            "ssl-certificate-location" => &self.ssl_certificate_location,
+            "ssl-key-location" => &self.ssl_key_location,
+            "ssl-key-password" => &self.ssl_key_password,
+            "sasl-mechanism" => &self.sasl_mechanism,
+            "sasl-username" => &self.sasl_username,
+            "sasl-password" => &self.sasl_password,
+            "sasl-oauth-token-command" => &self.sasl_oauth_token_command,
+            "config-file" => &self.config_file,
+            "schema-registry" => &self.schema_registry,
+            _ => return None,
+        };
+        value.clone()
+    }
+}
+
+pub fn context_file_path() -> PathBuf {
+    if let Ok(path) = env::var("K12_CONTEXT_FILE") {
+        return PathBuf::from(path);
+    }
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env::var("HOME").unwrap_or_else(|_| ".".to_string())).join(".config")
+        });
+    config_home.join("k12").join("config.toml")
+}
+
+/// Load the contexts file. A missing file has no contexts.
+pub fn load_context_file() -> ContextFile {
+    let path = context_file_path();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return ContextFile::default(),
+        Err(err) => {
+            eprintln!("Could not read {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    };
+    toml::from_str(&content).unwrap_or_else(|err| {
+        eprintln!("Invalid contexts file {}: {}", path.display(), err);
+        std::process::exit(1);
+    })
+}
+
+/// Global options resolved against the selected context: options given on
+/// the command line first, then the context, then environment variables and
+/// defaults.
+pub struct Settings<'a> {
+    pub matches: &'a ArgMatches<'static>,
+    pub context: Option<(String, Context)>,
+}
+
+impl<'a> Settings<'a> {
+    pub fn new(matches: &'a ArgMatches<'static>) -> Self {
+        let mut file = load_context_file();
+        let name = matches
+            .value_of("context")
+            .map(ToString::to_string)
+            .or_else(|| file.current_context.clone());
+        let context = name.map(|name| match file.contexts.remove(&name) {
+            Some(context) => (name, context),
+            None => {
+                eprintln!("No context {name} in {}.", context_file_path().display());
+                std::process::exit(1);
+            }
+        });
+        Settings { matches, context }
+    }
+
+    pub fn value_of(&self, arg: &str) -> Option<String> {
+        let given = self.matches.occurrences_of(arg) > 0;
+        let from_context = || {
+            self.context
+                .as_ref()
+                .and_then(|(_, context)| context.get(arg))
+        };
+        match given {
+            true => self.matches.value_of(arg).map(ToString::to_string),
+            false => from_context().or_else(|| self.matches.value_of(arg).map(ToString::to_string)),
+        }
+    }
+
+    /// The context's librdkafka properties.
+    pub fn properties(&self) -> Vec<(String, String)> {
+        self.context
+            .as_ref()
+            .map(|(_, context)| context.properties.clone().into_iter().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,334 @@
+use rdkafka::message::{Headers, Message};
+use regex::bytes::Regex;
+use serde_json::Value;
+use std::cmp::Ordering;
+
+/// The `--filter` expressions of `read` and `tail`. A message is shown only
+/// when it matches all of them.
+#[derive(Default)]
+pub struct Filters {
+    filters: Vec<Filter>,
+}
+
+enum Filter {
+    /// `payload~REGEX`, `key!~REGEX`
+    Regex {
+        on_key: bool,
+        regex: Regex,
+        negate: bool,
+    },
+    /// `.path`, `.path OP VALUE`
+    Json {
+        pointer: String,
+        comparison: Option<(Op, Value)>,
+    },
+    /// `header:NAME`, `header:NAME==VALUE`, `header:NAME!=VALUE`
+    Header {
+        name: String,
+        value: Option<String>,
+        negate: bool,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    /// Longest first so `>=` isn't taken for `>`.
+    const ALL: [(&'static str, Op); 6] = [
+        ("==", Op::Eq),
+        ("!=", Op::Ne),
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+    ];
+}
+
+impl Filters {
+    pub fn parse<'a>(expressions: Option<impl Iterator<Item = &'a str>>) -> Self {
+        let filters = expressions
+            .map(|expressions| expressions.map(parse_filter).collect())
+            .unwrap_or_default();
+        Filters { filters }
+    }
+
+    pub fn matches<M: Message>(&self, m: &M) -> bool {
+        let mut payload_json: Option<Option<Value>> = None;
+        self.filters.iter().all(|filter| match filter {
+            Filter::Regex {
+                on_key,
+                regex,
+                negate,
+            } => {
+                let bytes = match on_key {
+                    true => m.key(),
+                    false => m.payload(),
+                };
+                bytes.is_some_and(|bytes| regex.is_match(bytes)) != *negate
+            }
+            Filter::Json {
+                pointer,
+                comparison,
+            } => {
+                let json = payload_json.get_or_insert_with(|| {
+                    m.payload()
+                        .and_then(|payload| serde_json::from_slice(payload).ok())
+                });
+                let found = json.as_ref().and_then(|json| json.pointer(pointer));
+                match (found, comparison) {
+                    (None, Some((Op::Ne, _))) => true,
+                    (None, _) => false,
+                    (Some(found), None) => !matches!(found, Value::Null | Value::Bool(false)),
+                    (Some(found), Some((op, expected))) => compare(found, *op, expected),
+                }
+            }
+            Filter::Header {
+                name,
+                value,
+                negate,
+            } => {
+                let found = m.headers().is_some_and(|headers| {
+                    headers.iter().any(|header| {
+                        header.key == name
+                            && value
+                                .as_ref()
+                                .is_none_or(|value| header.value == Some(value.as_bytes()))
+                    })
+                });
+                found != *negate
+            }
+        })
+    }
+}
+
+fn parse_filter(expression: &str) -> Filter {
+    let expression = expression.trim();
+    let invalid = |reason: &str| -> ! {
+        eprintln!("Invalid filter {expression}: {reason}");
+        std::process::exit(1);
+    };
+    if let Some(header) = expression.strip_prefix("header:") {
+        // Whichever operator comes first, so values may contain the other.
+        let split = [("!=", true), ("==", false)]
+            .into_iter()
+            .filter_map(|(token, negate)| header.find(token).map(|at| (at, token, negate)))
+            .min_by_key(|(at, _, _)| *at);
+        let (name, value, negate) = match split {
+            Some((at, token, negate)) => (&header[..at], Some(&header[at + token.len()..]), negate),
+            None => (header, None, false),
+        };
+        if name.trim().is_empty() {
+            invalid("missing header name");
+        }
+        return Filter::Header {
+            name: name.trim().to_string(),
+            value: value.map(|value| value.trim().to_string()),
+            negate,
+        };
+    }
+    if let Some(path) = expression.strip_prefix('.') {
+        let split = Op::ALL
+            .iter()
+            .filter_map(|(token, op)| path.find(token).map(|at| (at, *token, *op)))
+            .min_by_key(|(at, _, _)| *at);
+        let (path, comparison) = match split {
+            Some((at, token, op)) => {
+                let literal = path[at + token.len()..].trim();
+                // Anything that isn't a json literal is taken as a string.
+                let expected = serde_json::from_str(literal)
+                    .unwrap_or_else(|_| Value::String(literal.to_string()));
+                (path[..at].trim(), Some((op, expected)))
+            }
+            None => (path.trim(), None),
+        };
+        let pointer = json_pointer(path).unwrap_or_else(|reason| invalid(&reason));
+        return Filter::Json {
+            pointer,
+            comparison,
+        };
+    }
+    for (target, on_key) in [("payload", false), ("key", true)] {
+        let Some(rest) = expression.strip_prefix(target) else {
+            continue;
+        };
+        let (pattern, negate) = if let Some(pattern) = rest.strip_prefix("!~") {
+            (pattern, true)
+        } else if let Some(pattern) = rest.strip_prefix('~') {
+            (pattern, false)
+        } else {
+            continue;
+        };
+        let regex = Regex::new(pattern).unwrap_or_else(|err| invalid(&err.to_string()));
+        return Filter::Regex {
+            on_key,
+            regex,
+            negate,
+        };
+    }
+    invalid("expected payload~REGEX, key~REGEX, .path OP VALUE or header:NAME==VALUE")
+}
+
+/// Turn `customer.id` or `items[0].sku` into a json pointer.
+pub(crate) fn json_pointer(path: &str) -> Result<String, String> {
+    let mut pointer = String::new();
+    if path.is_empty() {
+        return Ok(pointer);
+    }
+    for segment in path.split('.') {
+        let (name, indices) = match segment.find('[') {
+            Some(at) => segment.split_at(at),
+            None => (segment, ""),
+        };
+        if name.is_empty() && indices.is_empty() {
+            return Err("empty path segment".to_string());
+        }
+        if !name.is_empty() {
+            pointer.push('/');
+            pointer.push_str(&name.replace('~', "~0").replace('/', "~1"));
+        }
+        for index in indices.split_terminator(']') {
+            let index = index
+                .strip_prefix('[')
+                .filter(|index| index.parse::<usize>().is_ok())
+                .ok_or_else(|| format!("invalid index in {segment}"))?;
+            pointer.push('/');
+            pointer.push_str(index);
+        }
+    }
+    Ok(pointer)
+}
+
+/// Numbers compare as numbers and strings as strings. Other values only
+/// support equality.
+fn compare(found: &Value, op: Op, expected: &Value) -> bool {
+    let ordering = match (found, expected) {
+        (Value::Number(a), Value::Number(b)) => a
+            .as_f64()
+            .zip(b.as_f64())
+            .and_then(|(a, b)| a.partial_cmp(&b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ if found == expected => Some(Ordering::Equal),
+        _ => None,
+    };
+    match (op, ordering) {
+        (Op::Eq, ordering) => ordering == Some(Ordering::Equal),
+        (Op::Ne, ordering) => ordering != Some(Ordering::Equal),
+        (_, None) => false,
+        (Op::Gt, Some(ordering)) => ordering == Ordering::Greater,
+        (Op::Ge, Some(ordering)) => ordering != Ordering::Less,
+        (Op::Lt, Some(ordering)) => ordering == Ordering::Less,
+        (Op::Le, Some(ordering)) => ordering != Ordering::Greater,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rdkafka::message::{Header, OwnedHeaders, OwnedMessage, Timestamp};
+
+    fn message(payload: &str, key: Option<&str>, headers: &[(&str, &str)]) -> OwnedMessage {
+        let headers = headers
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(*value),
+                })
+            });
+        OwnedMessage::new(
+            Some(payload.as_bytes().to_vec()),
+            key.map(|key| key.as_bytes().to_vec()),
+            "orders".to_string(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            Some(headers),
+        )
+    }
+
+    fn matches(expression: &str, m: &OwnedMessage) -> bool {
+        Filters::parse(Some([expression].into_iter())).matches(m)
+    }
+
+    #[test]
+    fn comparisons() {
+        let m = message(r#"{"n": 5, "name": "b"}"#, None, &[]);
+        assert!(matches(".n >= 5", &m));
+        assert!(!matches(".n > 5", &m));
+        assert!(matches(".n <= 5", &m));
+        assert!(!matches(".n < 5", &m));
+        assert!(matches(".n == 5", &m));
+        assert!(matches(".n != 4", &m));
+        assert!(matches(".name > a", &m));
+        assert!(matches(".name == \"b\"", &m));
+        assert!(!matches(".n == \"5\"", &m));
+    }
+
+    #[test]
+    fn operators_inside_values() {
+        let m = message(r#"{"expr": "a>=b", "eq": "x==y"}"#, None, &[]);
+        assert!(matches(r#".expr == "a>=b""#, &m));
+        assert!(matches(r#".eq == "x==y""#, &m));
+        assert!(matches(r#".eq != "x""#, &m));
+    }
+
+    #[test]
+    fn missing_paths() {
+        let m = message(r#"{"a": {"b": [1, {"c": true}]}}"#, None, &[]);
+        assert!(matches(".a.b[1].c", &m));
+        assert!(!matches(".a.x", &m));
+        assert!(matches(".a.x != 1", &m));
+        assert!(!matches(".a.x == 1", &m));
+        assert!(!matches(".a", &message("not json", None, &[])));
+    }
+
+    #[test]
+    fn regexes() {
+        let m = message("order shipped", Some("k-1"), &[]);
+        assert!(matches("payload~ship", &m));
+        assert!(!matches("payload!~ship", &m));
+        assert!(matches("key~^k-\\d$", &m));
+        assert!(!matches("key~^x", &m));
+    }
+
+    #[test]
+    fn headers() {
+        let m = message("", None, &[("trace", "a==b"), ("env", "prod")]);
+        assert!(matches("header:trace", &m));
+        assert!(matches("header:env==prod", &m));
+        assert!(!matches("header:env!=prod", &m));
+        assert!(matches("header:trace==a==b", &m));
+        assert!(matches("header:env!=a==b", &m));
+        assert!(!matches("header:missing", &m));
+    }
+
+    #[test]
+    fn all_filters_must_match() {
+        let m = message(r#"{"n": 5}"#, Some("k"), &[]);
+        let filters = Filters::parse(Some([".n == 5", "key~k"].into_iter()));
+        assert!(filters.matches(&m));
+        let filters = Filters::parse(Some([".n == 5", "key~x"].into_iter()));
+        assert!(!filters.matches(&m));
+        assert!(Filters::parse(None::<std::iter::Empty<&str>>).matches(&m));
+    }
+
+    #[test]
+    fn pointers() {
+        assert_eq!(json_pointer(""), Ok(String::new()));
+        assert_eq!(json_pointer("customer.id"), Ok("/customer/id".to_string()));
+        assert_eq!(json_pointer("items[0].sku"), Ok("/items/0/sku".to_string()));
+        assert_eq!(json_pointer("m[1][2]"), Ok("/m/1/2".to_string()));
+        assert_eq!(json_pointer("a/b.c~d"), Ok("/a~1b/c~0d".to_string()));
+        assert!(json_pointer("a..b").is_err());
+        assert!(json_pointer("a[x]").is_err());
+        assert!(json_pointer("a[-1]").is_err());
+    }
+}
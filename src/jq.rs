@@ -0,0 +1,72 @@
+use jaq_core::load::{Arena, File, Loader};
+use jaq_core::{Compiler, Ctx, Filter, Native, RcIter};
+use jaq_json::Val;
+use serde_json::Value;
+
+/// What to do with the output of `--select` and `--transform`.
+pub enum JqMode {
+    /// Print only the results, one json value per line.
+    Select,
+    /// Print every result as the message payload, in the usual format.
+    Transform,
+}
+
+/// A compiled jq expression run on json payloads.
+pub struct Jq {
+    filter: Filter<Native<Val>>,
+    pub mode: JqMode,
+}
+
+impl Jq {
+    pub fn compile(code: &str, mode: JqMode) -> Self {
+        let invalid = |errors: String| -> ! {
+            eprintln!("Invalid jq expression {code}: {errors}");
+            std::process::exit(1);
+        };
+        let arena = Arena::default();
+        let loader = Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+        let modules = loader
+            .load(&arena, File { code, path: () })
+            .unwrap_or_else(|errors| {
+                invalid(
+                    errors
+                        .into_iter()
+                        .map(|(_, error)| format!("{error:?}"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+            });
+        let filter = Compiler::default()
+            .with_funs(jaq_std::funs().chain(jaq_json::funs()))
+            .compile(modules)
+            .unwrap_or_else(|errors| {
+                invalid(
+                    errors
+                        .into_iter()
+                        .flat_map(|(_, errors)| errors)
+                        .map(|(name, _)| format!("{name} is not defined"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+            });
+        Jq { filter, mode }
+    }
+
+    /// The expression of `--select` or `--transform`, whichever is given.
+    pub fn from_args(select: Option<&str>, transform: Option<&str>) -> Option<Self> {
+        match (select, transform) {
+            (Some(code), _) => Some(Jq::compile(code, JqMode::Select)),
+            (None, Some(code)) => Some(Jq::compile(code, JqMode::Transform)),
+            (None, None) => None,
+        }
+    }
+
+    /// Every value the expression yields for `input`.
+    pub fn run(&self, input: Value) -> Result<Vec<Value>, String> {
+        let inputs = RcIter::new(core::iter::empty());
+        self.filter
+            .run((Ctx::new([], &inputs), Val::from(input)))
+            .map(|output| output.map(Value::from).map_err(|err| err.to_string()))
+            .collect()
+    }
+}
@@ -1,30 +1,104 @@
-use clap::value_t;
-use rdkafka::config::ClientConfig;
+use clap::ArgMatches;
 use rdkafka::consumer::stream_consumer::StreamConsumer;
 use rdkafka::consumer::BaseConsumer;
 use rdkafka::producer::FutureProducer;
 use rdkafka::util::get_rdkafka_version;
 use std::env;
-use std::io::Read;
 use std::time::Duration;
 
 mod action;
+mod avro;
 mod cli;
+mod client;
 mod common;
+mod context;
+mod filter;
+mod jq;
+mod template;
 
-use common::{kafka_debug_from_int, Format, FormatConfig, FormatHint, Verbosity};
+use avro::Avro;
+use client::{K12Context, KafkaConfig};
+use common::{kafka_debug_from_int, Encoding, Field, Format, FormatConfig, FormatHint, Verbosity};
+use context::Settings;
+use template::Template;
 
 #[tokio::main]
 async fn main() {
     let matches = cli::app().get_matches();
     let verbosity = Verbosity::from(matches.occurrences_of("verbose") as u8);
+    let format_hint: Option<FormatHint> =
+        matches.value_of("format-hint").map(|format| format.into());
+
+    let payload_encoding: Encoding = matches
+        .value_of("payload-encoding")
+        .map(|encoding| encoding.into())
+        .unwrap_or(Encoding::Utf8);
+    let key_encoding: Encoding = matches
+        .value_of("key-encoding")
+        .map(|encoding| encoding.into())
+        .unwrap_or(Encoding::Utf8);
+    let header_encoding: Encoding = matches
+        .value_of("header-encoding")
+        .map(|encoding| encoding.into())
+        .unwrap_or(Encoding::Lossy);
+
+    let fields = matches
+        .values_of("fields")
+        .map(Field::parse_list)
+        .unwrap_or_else(|| Field::DEFAULT.to_vec());
+
+    let format_config = FormatConfig {
+        format_hint,
+        verbosity,
+        format: Format::Json,
+        show_topic: false,
+        payload_encoding,
+        key_encoding,
+        header_encoding,
+        fields,
+        jq: None,
+        template: None,
+        avro: None,
+    };
+
+    // Runs before the context is loaded so a broken one can still be switched away from.
+    if let ("context", Some(context_matches)) = matches.subcommand() {
+        let format_config = format_config.text_by_default(matches.value_of("format"));
+        action::context(matches.value_of("context"), format_config, context_matches);
+        return;
+    }
+
+    let settings = Settings::new(&matches);
+    let base_config = base_config(&settings);
+    // The token refresh callback is fixed by the client's context type, so
+    // everything that creates clients is built for both.
+    match base_config.oauth() {
+        true => run::<true>(&matches, &settings, base_config, format_config).await,
+        false => run::<false>(&matches, &settings, base_config, format_config).await,
+    }
+}
+
+async fn run<const OAUTH: bool>(
+    matches: &ArgMatches<'static>,
+    settings: &Settings<'_>,
+    base_config: KafkaConfig,
+    format_config: FormatConfig,
+) {
     let debug_level = kafka_debug_from_int(matches.occurrences_of("debug") as u8);
-    let group = matches.value_of("group");
-    let kafka_client_id = matches.value_of("client-id").map(ToString::to_string);
-    let brokers = matches
+    let group = settings.value_of("group");
+    let kafka_client_id = settings.value_of("client-id");
+    let brokers = settings
         .value_of("brokers")
-        .expect("Brokers in kaf)ka format");
-    let timeout = value_t!(matches, "timeout", u64).unwrap();
+        .expect("Brokers in kafka format");
+    let brokers = brokers.as_str();
+    let timeout = settings
+        .value_of("timeout")
+        .expect("timeout has a default")
+        .parse::<u64>()
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid timeout: {}", err);
+            std::process::exit(1);
+        });
     let timeout = Duration::from_millis(timeout);
 
     let user_id = env::var("USER").unwrap_or_else(|_| "unknown".to_string());
@@ -43,51 +117,71 @@ async fn main() {
         .unwrap();
 
     let group = group
-        .map(ToString::to_string)
         .or_else(|| Some(format!("{kafka_client_id}_group")))
         .expect("Group id set");
-    if verbosity >= Verbosity::TooMuch {
+    if format_config.verbosity >= Verbosity::TooMuch {
         let (version_n, version_s) = get_rdkafka_version();
         println!("rd_kafka_version: 0x{:08x}, {}", version_n, version_s);
     }
-    let format_hint: Option<FormatHint> =
-        matches.value_of("format-hint").map(|format| format.into());
-
-    let format: Format = matches
-        .value_of("format")
-        .map(|format| format.into())
-        .unwrap_or_else(|| Format::Json);
-
+    let format = settings.value_of("format");
+    let template = match (format.as_deref(), matches.value_of("template")) {
+        (Some("template"), Some(template)) => {
+            Some(Template::parse(template).unwrap_or_else(|err| {
+                eprintln!("Invalid template: {err}");
+                std::process::exit(1);
+            }))
+        }
+        (Some("template"), None) => {
+            eprintln!("--format template needs --template.");
+            std::process::exit(1);
+        }
+        (_, Some(_)) => {
+            eprintln!("--template needs --format template.");
+            std::process::exit(1);
+        }
+        (_, None) => None,
+    };
+    let avro = (format_config.format_hint == Some(FormatHint::Avro)).then(|| {
+        Avro::new(
+            settings.value_of("schema-registry"),
+            matches.value_of("schema-dir").map(ToString::to_string),
+            timeout,
+        )
+    });
     let format_config = FormatConfig {
-        format_hint,
-        verbosity,
-        format,
+        format: format.as_deref().map(Format::from).unwrap_or(Format::Json),
+        template,
+        avro,
+        ..format_config
     };
 
     match matches.subcommand() {
         ("write", Some(matches)) => {
-            let producer: FutureProducer = ClientConfig::new()
+            let producer: FutureProducer<K12Context<OAUTH>> = base_config
+                .clone()
                 .set("bootstrap.servers", brokers)
-                .set("message.timeout.ms", "5000")
                 .set("message.timeout.ms", format!("{}", timeout.as_millis()))
                 .create()
                 .expect("Producer creation error");
-            let topic = matches.value_of("topic").unwrap_or_else(|| {
-                eprintln!("topic is required");
-                std::process::exit(1);
-            });
-            let mut buf = String::new();
-            std::io::stdin()
-                .read_to_string(&mut buf)
-                .expect("could not read stdin");
-            let buf = buf.strip_suffix("\n").expect("could not read stdin");
-            action::produce(producer, topic, &buf).await;
+            let topic = matches.value_of("topic");
+            action::write(
+                producer,
+                topic,
+                format_config.payload_encoding,
+                format_config.key_encoding,
+                format_config.header_encoding,
+                matches,
+            )
+            .await;
         }
-        ("list", Some(_)) => {
-            let consumer: BaseConsumer = ClientConfig::new()
+        ("list", Some(matches)) => {
+            let consumer: BaseConsumer<K12Context<OAUTH>> = base_config
+                .clone()
                 .set("group.id", group)
                 .set("client.id", kafka_client_id)
                 .set("bootstrap.servers", brokers)
+                .set("enable.partition.eof", "true")
+                .set("enable.auto.commit", "false")
                 .set_log_level(debug_level)
                 .create()
                 .unwrap_or_else(|err| {
@@ -97,14 +191,74 @@ async fn main() {
                     );
                     std::process::exit(1);
                 });
-            action::list(consumer, timeout, format_config.verbosity);
+            let format_config = format_config.text_by_default(format.as_deref());
+            action::list(consumer, timeout, format_config, matches);
+        }
+        ("groups", Some(matches)) => {
+            let mut config = base_config.clone();
+            config
+                .set("group.id", group)
+                .set("client.id", kafka_client_id)
+                .set("bootstrap.servers", brokers)
+                .set("enable.auto.commit", "false")
+                .set_log_level(debug_level);
+            let format_config = format_config.text_by_default(format.as_deref());
+            action::groups::<OAUTH>(config, timeout, format_config, matches);
+        }
+        ("reset-offsets", Some(matches)) => {
+            let mut config = base_config.clone();
+            config
+                .set("group.id", group)
+                .set("client.id", kafka_client_id)
+                .set("bootstrap.servers", brokers)
+                .set("enable.auto.commit", "false")
+                .set_log_level(debug_level);
+            let format_config = format_config.text_by_default(format.as_deref());
+            action::reset_offsets::<OAUTH>(config, timeout, format_config, matches);
+        }
+        ("topic", Some(matches)) => {
+            let mut config = base_config.clone();
+            config
+                .set("client.id", kafka_client_id)
+                .set("bootstrap.servers", brokers)
+                .set_log_level(debug_level);
+            action::topic::<OAUTH>(config, timeout, matches).await;
+        }
+        ("config", Some(matches)) => {
+            let mut config = base_config.clone();
+            config
+                .set("client.id", kafka_client_id)
+                .set("bootstrap.servers", brokers)
+                .set_log_level(debug_level);
+            let format_config = format_config.text_by_default(format.as_deref());
+            action::config::<OAUTH>(config, timeout, format_config, matches).await;
+        }
+        ("truncate", Some(matches)) => {
+            let consumer: BaseConsumer<K12Context<OAUTH>> = base_config
+                .clone()
+                .set("group.id", group)
+                .set("client.id", kafka_client_id)
+                .set("bootstrap.servers", brokers)
+                .set("enable.auto.commit", "false")
+                .set_log_level(debug_level)
+                .create()
+                .unwrap_or_else(|err| {
+                    eprintln!(
+                        "Could not create consumer from broker list {} : {}",
+                        brokers, err
+                    );
+                    std::process::exit(1);
+                });
+            let format_config = format_config.text_by_default(format.as_deref());
+            action::truncate(consumer, timeout, format_config, matches);
         }
         ("read", Some(matches)) => {
-            let consumer: BaseConsumer = ClientConfig::new()
+            let consumer: BaseConsumer<K12Context<OAUTH>> = base_config
+                .clone()
                 .set("group.id", group)
                 .set("client.id", kafka_client_id)
                 .set("bootstrap.servers", brokers)
-                .set("enable.partition.eof", "false")
+                .set("enable.partition.eof", "true")
                 .set("session.timeout.ms", format!("{}", timeout.as_millis()))
                 .set("enable.auto.commit", "false")
                 .set("auto.offset.reset", "earliest")
@@ -119,16 +273,33 @@ async fn main() {
                     std::process::exit(1);
                 });
 
-            action::read(consumer, format_config, timeout, &matches);
+            action::read(consumer, format_config, timeout, matches);
         }
         ("tail", Some(match_list)) => {
-            let consumer: StreamConsumer = ClientConfig::new()
-                .set("group.id", group)
+            let mut config = base_config.clone();
+            // librdkafka needs a group.id to assign or subscribe at all. Without
+            // --commit-group a throwaway one that never commits leaves no
+            // offsets behind, and only a pattern subscription joins it.
+            let tail_group = match match_list.value_of("commit-group") {
+                Some(group) => group.to_string(),
+                None => format!("k12-tail-{kafka_client_id}-{}", std::process::id()),
+            };
+            config.set("group.id", tail_group);
+            if match_list.is_present("topic-pattern") {
+                // Notice new matching topics within seconds rather than minutes.
+                config.set("topic.metadata.refresh.interval.ms", "10000");
+            }
+            let offset_reset = match match_list.is_present("from-beginning") {
+                true => "earliest",
+                false => "latest",
+            };
+            let consumer: StreamConsumer<K12Context<OAUTH>> = config
                 .set("client.id", kafka_client_id)
                 .set("bootstrap.servers", brokers)
                 .set("enable.partition.eof", "false")
                 .set("session.timeout.ms", format!("{}", timeout.as_millis()))
                 .set("enable.auto.commit", "false")
+                .set("auto.offset.reset", offset_reset)
                 .set_log_level(debug_level)
                 .create()
                 .unwrap_or_else(|err| {
@@ -138,16 +309,41 @@ async fn main() {
                     );
                     std::process::exit(1);
                 });
-            let topics: Vec<&str> = match_list
-                .values_of("topic")
-                .or_else(|| {
-                    eprintln!("No topic provided.",);
-                    std::process::exit(1);
-                })
-                .expect("topics")
-                .collect();
-            action::tail(consumer, format_config, topics).await;
+            action::tail(consumer, format_config, timeout, match_list).await;
         }
         _ => unreachable!(),
     };
 }
+
+/// A client config with the context's properties, the security options over
+/// them, and the user's own properties from `--config-file` and `-X` to apply
+/// last. Every client k12 creates starts from this.
+fn base_config(settings: &Settings) -> KafkaConfig {
+    let token_command = settings.value_of("sasl-oauth-token-command");
+    let mut overrides = Vec::new();
+    if let Some(config_file) = settings.value_of("config-file") {
+        overrides.extend(client::read_properties(&config_file));
+    }
+    if let Some(properties) = settings.matches.values_of("property") {
+        overrides.extend(properties.map(client::parse_property));
+    }
+    let mut config = KafkaConfig::new(token_command, overrides);
+    for (key, value) in settings.properties() {
+        config.set(key, value);
+    }
+    for (arg, key) in [
+        ("security-protocol", "security.protocol"),
+        ("ssl-ca-location", "ssl.ca.location"),
+        ("ssl-certificate-location", "ssl.certificate.location"),
+        ("ssl-key-location", "ssl.key.location"),
+        ("ssl-key-password", "ssl.key.password"),
+        ("sasl-mechanism", "sasl.mechanism"),
+        ("sasl-username", "sasl.username"),
+        ("sasl-password", "sasl.password"),
+    ] {
+        if let Some(value) = settings.value_of(arg) {
+            config.set(key, value);
+        }
+    }
+    config
+}
@@ -0,0 +1,278 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{TimeZone, Utc};
+
+use crate::filter::json_pointer;
+
+/// A `--template` such as `{{partition}}:{{offset}} {{payload | json_path(".id")}}`,
+/// parsed once and rendered for every message.
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder, Vec<TemplateFilter>),
+}
+
+/// The message values a template can refer to.
+pub enum Placeholder {
+    Topic,
+    Partition,
+    Offset,
+    Key,
+    Payload,
+    Timestamp,
+    TimestampType,
+    /// All headers as json.
+    Headers,
+    /// The value of the first header with this name.
+    Header(String),
+}
+
+enum TemplateFilter {
+    JsonPath(String),
+    Date(String),
+    Default(String),
+    Upper,
+    Lower,
+    Truncate(usize),
+    Json,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Literal(unescape(&rest[..start])));
+            }
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(format!("unclosed {{{{ in {}", &rest[start..]));
+            };
+            let expression = &rest[start + 2..start + end];
+            let mut parts = split_pipes(expression).into_iter();
+            let name = parts.next().unwrap_or_default();
+            let placeholder = parse_placeholder(name.trim())?;
+            let filters = parts
+                .map(|filter| parse_filter(filter.trim()))
+                .collect::<Result<_, _>>()?;
+            segments.push(Segment::Placeholder(placeholder, filters));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(unescape(rest)));
+        }
+        Ok(Template { segments })
+    }
+
+    /// Fill in the template. `value` gives the value of a placeholder, or
+    /// `None` when the message doesn't have it.
+    pub fn render(&self, value: impl Fn(&Placeholder) -> Option<String>) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => out.push_str(literal),
+                Segment::Placeholder(placeholder, filters) => {
+                    let value = filters
+                        .iter()
+                        .fold(value(placeholder), |value, filter| filter.apply(value));
+                    out.push_str(value.as_deref().unwrap_or_default());
+                }
+            }
+        }
+        out
+    }
+}
+
+impl TemplateFilter {
+    fn apply(&self, value: Option<String>) -> Option<String> {
+        match self {
+            TemplateFilter::Default(default) => value.or_else(|| Some(default.clone())),
+            TemplateFilter::JsonPath(pointer) => {
+                let json: serde_json::Value = serde_json::from_str(&value?).ok()?;
+                match json.pointer(pointer)? {
+                    serde_json::Value::String(string) => Some(string.clone()),
+                    serde_json::Value::Null => None,
+                    found => Some(found.to_string()),
+                }
+            }
+            TemplateFilter::Date(format) => {
+                let millis = value?.parse::<i64>().ok()?;
+                let time = Utc.timestamp_millis_opt(millis).single()?;
+                Some(time.format(format).to_string())
+            }
+            TemplateFilter::Upper => value.map(|value| value.to_uppercase()),
+            TemplateFilter::Lower => value.map(|value| value.to_lowercase()),
+            TemplateFilter::Truncate(len) => value.map(|value| value.chars().take(*len).collect()),
+            TemplateFilter::Json => Some(serde_json::Value::from(value).to_string()),
+        }
+    }
+}
+
+fn parse_placeholder(name: &str) -> Result<Placeholder, String> {
+    let placeholder = match name {
+        "topic" => Placeholder::Topic,
+        "partition" => Placeholder::Partition,
+        "offset" => Placeholder::Offset,
+        "key" => Placeholder::Key,
+        "payload" => Placeholder::Payload,
+        "timestamp" => Placeholder::Timestamp,
+        "timestamp_type" => Placeholder::TimestampType,
+        "headers" => Placeholder::Headers,
+        _ => match name.strip_prefix("header.") {
+            Some(header) if !header.is_empty() => Placeholder::Header(header.to_string()),
+            _ => return Err(format!("unknown placeholder {name}")),
+        },
+    };
+    Ok(placeholder)
+}
+
+fn parse_filter(filter: &str) -> Result<TemplateFilter, String> {
+    let (name, argument) = match filter.split_once('(') {
+        Some((name, argument)) => {
+            let argument = argument
+                .strip_suffix(')')
+                .ok_or_else(|| format!("missing ) in {filter}"))?
+                .trim();
+            let argument = argument
+                .strip_prefix('"')
+                .and_then(|argument| argument.strip_suffix('"'))
+                .unwrap_or(argument);
+            (name.trim(), Some(argument))
+        }
+        None => (filter, None),
+    };
+    let filter = match (name, argument) {
+        ("json_path", Some(path)) => {
+            TemplateFilter::JsonPath(json_pointer(path.strip_prefix('.').unwrap_or(path))?)
+        }
+        ("date", format) => {
+            let format = format.unwrap_or("%+");
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                return Err(format!("invalid date format {format}"));
+            }
+            TemplateFilter::Date(format.to_string())
+        }
+        ("default", Some(default)) => TemplateFilter::Default(default.to_string()),
+        ("upper", None) => TemplateFilter::Upper,
+        ("lower", None) => TemplateFilter::Lower,
+        ("truncate", Some(len)) => TemplateFilter::Truncate(
+            len.parse()
+                .map_err(|err| format!("invalid truncate length {len}: {err}"))?,
+        ),
+        ("json", None) => TemplateFilter::Json,
+        _ => return Err(format!("unknown filter {filter}")),
+    };
+    Ok(filter)
+}
+
+/// Split a placeholder on `|`, except inside double quotes.
+fn split_pipes(expression: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (idx, c) in expression.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '|' if !quoted => {
+                parts.push(&expression[start..idx]);
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&expression[start..]);
+    parts
+}
+
+/// Literal text may use `\n` and `\t`, which are awkward to pass in a shell.
+fn unescape(literal: &str) -> String {
+    literal.replace("\\n", "\n").replace("\\t", "\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str, payload: Option<&str>) -> String {
+        Template::parse(template)
+            .unwrap()
+            .render(|placeholder| match placeholder {
+                Placeholder::Partition => Some("0".to_string()),
+                Placeholder::Offset => Some("42".to_string()),
+                Placeholder::Timestamp => Some("1713508350144".to_string()),
+                Placeholder::Header(name) if name == "trace" => Some("abc".to_string()),
+                Placeholder::Payload => payload.map(ToString::to_string),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn placeholders() {
+        let payload = Some(r#"{"id": 7, "customer": {"name": "Ann"}}"#);
+        assert_eq!(
+            render(
+                r#"{{partition}}:{{offset}} {{payload | json_path(".id")}}"#,
+                payload
+            ),
+            "0:42 7"
+        );
+        assert_eq!(
+            render(
+                r#"{{ payload | json_path(".customer.name") | upper }}"#,
+                payload
+            ),
+            "ANN"
+        );
+        assert_eq!(render("{{header.trace}}|{{header.other}}", None), "abc|");
+        assert_eq!(render("{{key}}", None), "");
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(render("{{payload | lower}}", Some("ABC")), "abc");
+        assert_eq!(render("{{payload | truncate(2)}}", Some("abc")), "ab");
+        assert_eq!(render("{{payload | json}}", Some("a\"b")), r#""a\"b""#);
+        assert_eq!(render("{{payload | json}}", None), "null");
+        assert_eq!(render(r#"{{payload | default("none")}}"#, None), "none");
+        assert_eq!(render(r#"{{payload | default("none")}}"#, Some("x")), "x");
+        assert_eq!(
+            render(
+                r#"{{payload | json_path(".missing") | default("-")}}"#,
+                Some("{}")
+            ),
+            "-"
+        );
+        assert_eq!(
+            render(r#"{{timestamp | date("%Y-%m-%d")}}"#, None),
+            "2024-04-19"
+        );
+    }
+
+    #[test]
+    fn quoted_pipes_and_escapes() {
+        assert_eq!(render(r#"{{payload | default("a|b")}}"#, None), "a|b");
+        assert_eq!(render(r"{{partition}}\t{{offset}}\n", None), "0\t42\n");
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |template: &str| Template::parse(template).err().unwrap();
+        assert_eq!(error("{{payload | nope}}"), "unknown filter nope");
+        assert_eq!(error("{{payload | upper(1)}}"), "unknown filter upper(1)");
+        assert_eq!(error("{{payload | json_path}}"), "unknown filter json_path");
+        assert_eq!(error("a {{payload"), "unclosed {{ in {{payload");
+        assert_eq!(error("{{payloads}}"), "unknown placeholder payloads");
+        assert_eq!(error("{{header.}}"), "unknown placeholder header.");
+        assert!(error("{{payload | truncate(x)}}").starts_with("invalid truncate length x"));
+        assert_eq!(
+            error(r#"{{timestamp | date("%Q")}}"#),
+            "invalid date format %Q"
+        );
+        assert_eq!(
+            error(r#"{{payload | default("x"}}"#),
+            r#"missing ) in default("x""#
+        );
+    }
+}